@@ -0,0 +1,91 @@
+//! Compares the UNNEST and binary COPY writers.
+//!
+//! Needs a migrated database in TEST_DATABASE_URL; rows written by the
+//! benchmark are removed afterwards. Run with `cargo bench --bench insert_events`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+
+use telemetry_events::dimensions::DimensionIds;
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::telemetry_event::{encode_copy_binary, insert_events_copy, insert_events_unnest};
+
+const BENCH_METRIC_NAME: &str = "Bench.InsertEvents";
+const BATCH_SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+fn bench_events(count: usize) -> Vec<MyPayload> {
+    (0..count)
+        .map(|i| MyPayload {
+            cadence: "typical".to_string(),
+            channel: "release".to_string(),
+            country_code: "TH".to_string(),
+            metric_name: BENCH_METRIC_NAME.to_string(),
+            metric_value: (i % 8) as i32,
+            platform: "linux".to_string(),
+            version: "1.79.118".to_string(),
+            woi: Some(21),
+            wos: Some(25),
+            yoi: Some(2025),
+            yos: 2025,
+            idempotency_key: None,
+            extra: Default::default(),
+        })
+        .collect()
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_copy_binary");
+    for size in BATCH_SIZES {
+        let events = bench_events(size);
+        let ids = vec![DimensionIds::default(); size];
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &events, |b, events| {
+            b.iter(|| encode_copy_binary(events, &ids))
+        });
+    }
+    group.finish();
+}
+
+fn insert(c: &mut Criterion) {
+    dotenvy::dotenv().ok();
+    let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL not set, skipping insert benchmarks");
+        return;
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let pg_pool = rt.block_on(async {
+        PgPoolOptions::new()
+            .max_connections(2)
+            .connect(&database_url)
+            .await
+            .expect("Failed to connect to test database")
+    });
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+
+    let mut group = c.benchmark_group("insert_events");
+    group.sample_size(10);
+    for size in BATCH_SIZES {
+        let events = bench_events(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("unnest", size), &events, |b, events| {
+            b.iter(|| rt.block_on(insert_events_unnest(pool.clone(), events.clone())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("copy", size), &events, |b, events| {
+            b.iter(|| rt.block_on(insert_events_copy(pool.clone(), events.clone())).unwrap())
+        });
+    }
+    group.finish();
+
+    rt.block_on(async {
+        sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+            .bind(BENCH_METRIC_NAME)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    });
+}
+
+criterion_group!(benches, encode, insert);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS extension_packages;
+DROP TABLE IF EXISTS extension_versions;
+DROP TABLE IF EXISTS extension_apps;
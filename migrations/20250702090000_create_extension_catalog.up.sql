@@ -0,0 +1,33 @@
+CREATE TABLE extension_apps (
+                                appid TEXT PRIMARY KEY,
+                                cohort TEXT,
+                                cohortname TEXT,
+                                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE extension_versions (
+                                    id BIGSERIAL PRIMARY KEY,
+                                    appid TEXT NOT NULL REFERENCES extension_apps (appid) ON DELETE CASCADE,
+                                    version TEXT NOT NULL,
+                                    codebases TEXT[] NOT NULL,
+                                    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    UNIQUE (appid, version)
+);
+
+CREATE TABLE extension_packages (
+                                    id BIGSERIAL PRIMARY KEY,
+                                    version_id BIGINT NOT NULL REFERENCES extension_versions (id) ON DELETE CASCADE,
+                                    name TEXT NOT NULL,
+                                    hash_sha256 TEXT NOT NULL,
+                                    size BIGINT NOT NULL,
+                                    fp TEXT NOT NULL,
+                                    required BOOLEAN NOT NULL,
+                                    hash TEXT,
+                                    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    UNIQUE (version_id, name)
+);
+
+CREATE INDEX idx_extension_packages_hash ON extension_packages (hash_sha256);
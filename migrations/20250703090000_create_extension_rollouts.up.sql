@@ -0,0 +1,14 @@
+CREATE TABLE extension_rollouts (
+                                    appid TEXT NOT NULL,
+                                    channel TEXT NOT NULL,
+                                    platform TEXT NOT NULL,
+                                    version TEXT NOT NULL,
+                                    percentage SMALLINT NOT NULL CHECK (percentage BETWEEN 0 AND 100),
+                                    pinned BOOLEAN NOT NULL DEFAULT false,
+                                    cohort TEXT,
+                                    cohortname TEXT,
+                                    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                    PRIMARY KEY (appid, channel, platform),
+                                    FOREIGN KEY (appid, version) REFERENCES extension_versions (appid, version) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Idempotency keys of inserted events. Kept outside the partitioned table,
+-- whose unique constraints would have to include received_at.
+CREATE TABLE telemetry_event_keys (
+                                      idempotency_key TEXT PRIMARY KEY,
+                                      received_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_telemetry_event_keys_received_at ON telemetry_event_keys (received_at);
@@ -0,0 +1,10 @@
+CREATE TABLE telemetry_quarantine (
+                                      id BIGSERIAL PRIMARY KEY,
+                                      reason TEXT NOT NULL,
+                                      detail TEXT NOT NULL,
+                                      raw TEXT NOT NULL,
+                                      received_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                      resubmitted_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_telemetry_quarantine_pending ON telemetry_quarantine (reason, id) WHERE resubmitted_at IS NULL;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE telemetry_events DROP COLUMN IF EXISTS extra;
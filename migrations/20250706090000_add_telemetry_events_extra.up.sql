@@ -0,0 +1 @@
+ALTER TABLE telemetry_events ADD COLUMN extra JSONB;
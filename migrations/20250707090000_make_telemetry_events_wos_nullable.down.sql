@@ -0,0 +1,3 @@
+-- Add down migration script here
+UPDATE telemetry_events SET wos = 0 WHERE wos IS NULL;
+ALTER TABLE telemetry_events ALTER COLUMN wos SET NOT NULL;
@@ -0,0 +1,3 @@
+-- A missing survey week used to be written as 0, which is not a valid ISO week.
+ALTER TABLE telemetry_events ALTER COLUMN wos DROP NOT NULL;
+UPDATE telemetry_events SET wos = NULL WHERE wos = 0;
@@ -0,0 +1,50 @@
+-- Add down migration script here
+CREATE TABLE telemetry_events_denormalized (
+                                  id BIGSERIAL NOT NULL,
+                                  cadence TEXT NOT NULL,
+                                  channel TEXT NOT NULL,
+                                  country_code TEXT NOT NULL,
+                                  metric_name TEXT NOT NULL,
+                                  metric_value INTEGER NOT NULL,
+                                  platform TEXT NOT NULL,
+                                  version TEXT NOT NULL,
+                                  woi SMALLINT NOT NULL,
+                                  wos SMALLINT,
+                                  yoi SMALLINT NOT NULL,
+                                  yos SMALLINT NOT NULL,
+                                  received_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                  extra JSONB,
+                                  UNIQUE (id, received_at)
+) PARTITION BY RANGE (received_at);
+
+DO $$
+DECLARE
+    part RECORD;
+BEGIN
+    FOR part IN
+        SELECT substring(c.relname FROM '_y([0-9]+)$') AS year, pg_get_expr(c.relpartbound, c.oid) AS bound
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = 'telemetry_event_facts'::regclass AND c.relname ~ '_y[0-9]+$'
+    LOOP
+        EXECUTE format('CREATE TABLE telemetry_events_y%s PARTITION OF telemetry_events_denormalized %s', part.year, part.bound);
+        EXECUTE format('CREATE INDEX idx_metric_time_y%s ON telemetry_events_y%s (metric_name, received_at)', part.year, part.year);
+        EXECUTE format('CREATE INDEX idx_platform_y%s ON telemetry_events_y%s (platform)', part.year, part.year);
+    END LOOP;
+END $$;
+
+CREATE TABLE telemetry_events_default
+    PARTITION OF telemetry_events_denormalized DEFAULT;
+
+INSERT INTO telemetry_events_denormalized SELECT * FROM telemetry_events;
+
+SELECT setval(pg_get_serial_sequence('telemetry_events_denormalized', 'id'), COALESCE(MAX(id), 0) + 1, false)
+FROM telemetry_events_denormalized;
+
+DROP VIEW telemetry_events;
+DROP FUNCTION telemetry_events_delete();
+DROP TABLE telemetry_event_facts;
+DROP TABLE telemetry_dim_cadence, telemetry_dim_channel, telemetry_dim_country_code,
+    telemetry_dim_metric_name, telemetry_dim_platform, telemetry_dim_version;
+
+ALTER TABLE telemetry_events_denormalized RENAME TO telemetry_events;
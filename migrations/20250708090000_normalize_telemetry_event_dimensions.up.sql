@@ -0,0 +1,109 @@
+-- Low-cardinality text columns move to dimension tables referenced by integer
+-- ids. telemetry_events becomes a view with the previous column shape.
+CREATE TABLE telemetry_dim_cadence (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+CREATE TABLE telemetry_dim_channel (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+CREATE TABLE telemetry_dim_country_code (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+CREATE TABLE telemetry_dim_metric_name (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+CREATE TABLE telemetry_dim_platform (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+CREATE TABLE telemetry_dim_version (id SERIAL PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+
+INSERT INTO telemetry_dim_cadence (value) SELECT DISTINCT cadence FROM telemetry_events;
+INSERT INTO telemetry_dim_channel (value) SELECT DISTINCT channel FROM telemetry_events;
+INSERT INTO telemetry_dim_country_code (value) SELECT DISTINCT country_code FROM telemetry_events;
+INSERT INTO telemetry_dim_metric_name (value) SELECT DISTINCT metric_name FROM telemetry_events;
+INSERT INTO telemetry_dim_platform (value) SELECT DISTINCT platform FROM telemetry_events;
+INSERT INTO telemetry_dim_version (value) SELECT DISTINCT version FROM telemetry_events;
+
+CREATE TABLE telemetry_event_facts (
+                                       id BIGSERIAL NOT NULL,
+                                       cadence_id INTEGER NOT NULL REFERENCES telemetry_dim_cadence (id),
+                                       channel_id INTEGER NOT NULL REFERENCES telemetry_dim_channel (id),
+                                       country_code_id INTEGER NOT NULL REFERENCES telemetry_dim_country_code (id),
+                                       metric_name_id INTEGER NOT NULL REFERENCES telemetry_dim_metric_name (id),
+                                       metric_value INTEGER NOT NULL,
+                                       platform_id INTEGER NOT NULL REFERENCES telemetry_dim_platform (id),
+                                       version_id INTEGER NOT NULL REFERENCES telemetry_dim_version (id),
+                                       woi SMALLINT NOT NULL,
+                                       wos SMALLINT,
+                                       yoi SMALLINT NOT NULL,
+                                       yos SMALLINT NOT NULL,
+                                       extra JSONB,
+                                       received_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                                       UNIQUE (id, received_at)
+) PARTITION BY RANGE (received_at);
+
+-- Indexes on the parent cover existing and future partitions.
+CREATE INDEX idx_event_facts_metric_time ON telemetry_event_facts (metric_name_id, received_at);
+CREATE INDEX idx_event_facts_platform ON telemetry_event_facts (platform_id);
+
+-- Recreate every yearly partition of the old table.
+DO $$
+DECLARE
+    part RECORD;
+BEGIN
+    FOR part IN
+        SELECT substring(c.relname FROM '_y([0-9]+)$') AS year, pg_get_expr(c.relpartbound, c.oid) AS bound
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = 'telemetry_events'::regclass AND c.relname ~ '_y[0-9]+$'
+    LOOP
+        EXECUTE format('CREATE TABLE telemetry_event_facts_y%s PARTITION OF telemetry_event_facts %s', part.year, part.bound);
+    END LOOP;
+END $$;
+
+CREATE TABLE telemetry_event_facts_default
+    PARTITION OF telemetry_event_facts DEFAULT;
+
+INSERT INTO telemetry_event_facts (
+    id, cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+    platform_id, version_id, woi, wos, yoi, yos, extra, received_at
+)
+SELECT e.id, ca.id, ch.id, co.id, mn.id, e.metric_value,
+       pl.id, ve.id, e.woi, e.wos, e.yoi, e.yos, e.extra, e.received_at
+FROM telemetry_events e
+JOIN telemetry_dim_cadence ca ON ca.value = e.cadence
+JOIN telemetry_dim_channel ch ON ch.value = e.channel
+JOIN telemetry_dim_country_code co ON co.value = e.country_code
+JOIN telemetry_dim_metric_name mn ON mn.value = e.metric_name
+JOIN telemetry_dim_platform pl ON pl.value = e.platform
+JOIN telemetry_dim_version ve ON ve.value = e.version;
+
+SELECT setval(pg_get_serial_sequence('telemetry_event_facts', 'id'), COALESCE(MAX(id), 0) + 1, false)
+FROM telemetry_event_facts;
+
+DROP TABLE telemetry_events;
+
+CREATE VIEW telemetry_events AS
+SELECT f.id,
+       ca.value AS cadence,
+       ch.value AS channel,
+       co.value AS country_code,
+       mn.value AS metric_name,
+       f.metric_value,
+       pl.value AS platform,
+       ve.value AS version,
+       f.woi,
+       f.wos,
+       f.yoi,
+       f.yos,
+       f.received_at,
+       f.extra
+FROM telemetry_event_facts f
+JOIN telemetry_dim_cadence ca ON ca.id = f.cadence_id
+JOIN telemetry_dim_channel ch ON ch.id = f.channel_id
+JOIN telemetry_dim_country_code co ON co.id = f.country_code_id
+JOIN telemetry_dim_metric_name mn ON mn.id = f.metric_name_id
+JOIN telemetry_dim_platform pl ON pl.id = f.platform_id
+JOIN telemetry_dim_version ve ON ve.id = f.version_id;
+
+-- Keeps `DELETE FROM telemetry_events WHERE ...` working against the view.
+CREATE FUNCTION telemetry_events_delete() RETURNS trigger AS $$
+BEGIN
+    DELETE FROM telemetry_event_facts WHERE id = OLD.id AND received_at = OLD.received_at;
+    RETURN OLD;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER telemetry_events_delete
+    INSTEAD OF DELETE ON telemetry_events
+    FOR EACH ROW EXECUTE FUNCTION telemetry_events_delete();
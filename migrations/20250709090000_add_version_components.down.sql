@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE telemetry_dim_version
+    DROP COLUMN IF EXISTS major,
+    DROP COLUMN IF EXISTS minor,
+    DROP COLUMN IF EXISTS build,
+    DROP COLUMN IF EXISTS patch;
@@ -0,0 +1,19 @@
+-- Numeric components of each version, parsed by the writer for new values.
+ALTER TABLE telemetry_dim_version
+    ADD COLUMN major INTEGER,
+    ADD COLUMN minor INTEGER,
+    ADD COLUMN build INTEGER,
+    ADD COLUMN patch INTEGER;
+
+UPDATE telemetry_dim_version d
+SET major = m[1]::int, minor = m[2]::int, build = m[3]::int, patch = m[4]::int
+FROM (
+    SELECT id, regexp_match(
+        btrim(value),
+        '^[vV]?([0-9]{1,9})(?![0-9])(?:\.([0-9]{1,9})(?![0-9])(?:\.([0-9]{1,9})(?![0-9])(?:\.([0-9]{1,9})(?![0-9]))?)?)?'
+    ) AS m
+    FROM telemetry_dim_version
+) parsed
+WHERE parsed.id = d.id AND parsed.m IS NOT NULL;
+
+CREATE INDEX idx_dim_version_components ON telemetry_dim_version (major, minor, build, patch);
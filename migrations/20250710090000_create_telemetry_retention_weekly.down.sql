@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP MATERIALIZED VIEW IF EXISTS telemetry_retention_weekly;
@@ -0,0 +1,24 @@
+-- Reports per install cohort and week since install, refreshed by the server.
+CREATE MATERIALIZED VIEW telemetry_retention_weekly AS
+SELECT metric_name_id,
+       platform_id,
+       channel_id,
+       install_week,
+       (survey_week - install_week) / 7 AS weeks_since_install,
+       count(*) AS reports
+FROM (
+    SELECT metric_name_id,
+           platform_id,
+           channel_id,
+           to_date(yoi || '-' || woi, 'IYYY-IW') AS install_week,
+           to_date(yos || '-' || wos, 'IYYY-IW') AS survey_week
+    FROM telemetry_event_facts
+    WHERE wos BETWEEN 1 AND 53 AND woi BETWEEN 1 AND 53 AND yoi > 0 AND yos > 0
+) weeks
+WHERE survey_week >= install_week
+GROUP BY metric_name_id, platform_id, channel_id, install_week, weeks_since_install
+WITH DATA;
+
+-- Required by REFRESH MATERIALIZED VIEW CONCURRENTLY.
+CREATE UNIQUE INDEX idx_retention_weekly_cohort
+    ON telemetry_retention_weekly (metric_name_id, platform_id, channel_id, install_week, weeks_since_install);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+-- The cleared install weeks were the request weeks and are not restored.
@@ -0,0 +1,8 @@
+-- Omaha measurements used the request week as install week, which counted
+-- every update check as a new install in telemetry_retention_weekly.
+UPDATE telemetry_event_facts
+SET woi = 0, yoi = 0
+WHERE metric_name_id IN (SELECT id FROM telemetry_dim_metric_name WHERE value LIKE 'Omaha.%')
+  AND (woi, yoi) IS DISTINCT FROM (0, 0);
+
+REFRESH MATERIALIZED VIEW telemetry_retention_weekly;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+UPDATE telemetry_event_facts SET woi = 0, yoi = 0 WHERE woi IS NULL OR yoi IS NULL;
+ALTER TABLE telemetry_event_facts ALTER COLUMN woi SET NOT NULL;
+ALTER TABLE telemetry_event_facts ALTER COLUMN yoi SET NOT NULL;
@@ -0,0 +1,5 @@
+-- Omaha measurements have no install week and used to store 0, which is not
+-- a valid ISO week or year.
+ALTER TABLE telemetry_event_facts ALTER COLUMN woi DROP NOT NULL;
+ALTER TABLE telemetry_event_facts ALTER COLUMN yoi DROP NOT NULL;
+UPDATE telemetry_event_facts SET woi = NULL, yoi = NULL WHERE woi = 0 AND yoi = 0;
@@ -0,0 +1,287 @@
+//! Privacy-preserving access log.
+//!
+//! Replaces actix's `Logger`, which writes the client address of every
+//! submission. The format comes from `ACCESS_LOG_FORMAT` and supports:
+//!
+//! | placeholder | value                                  |
+//! |-------------|----------------------------------------|
+//! | `%r`        | request line, e.g. `POST /api/v1/p3a HTTP/1.1` |
+//! | `%m` `%U`   | method, path                           |
+//! | `%s`        | response status                        |
+//! | `%b`        | response body size in bytes            |
+//! | `%D` `%T`   | time taken in milliseconds, seconds    |
+//! | `%a`        | client address, see `ACCESS_LOG_IP`    |
+//! | `%{User-Agent}i` | user agent, see `ACCESS_LOG_USER_AGENT` |
+//!
+//! `ACCESS_LOG_IP` and `ACCESS_LOG_USER_AGENT` are `omit` or `truncate`.
+//! Addresses are truncated to their /24 (IPv4) or /48 (IPv6) network and user
+//! agents to their first product token. Addresses are omitted by default, and
+//! otherwise come from [`client_ip`](crate::client_ip::client_ip).
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::Error;
+use std::env;
+use std::future::{ready, Future, Ready};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::client_ip::client_ip;
+
+const ACCESS_LOG_FORMAT_ENV_KEY: &str = "ACCESS_LOG_FORMAT";
+const ACCESS_LOG_FORMAT_DEFAULT: &str = "\"%r\" %s %b %Dms";
+const ACCESS_LOG_IP_ENV_KEY: &str = "ACCESS_LOG_IP";
+const ACCESS_LOG_IP_DEFAULT: &str = "omit";
+const ACCESS_LOG_USER_AGENT_ENV_KEY: &str = "ACCESS_LOG_USER_AGENT";
+const ACCESS_LOG_USER_AGENT_DEFAULT: &str = "truncate";
+const USER_AGENT_PLACEHOLDER: &str = "%{User-Agent}i";
+const OMITTED: &str = "-";
+
+/// How a client identifying field is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    Omit,
+    Truncate,
+}
+
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "omit" => Ok(Redaction::Omit),
+            "truncate" => Ok(Redaction::Truncate),
+            other => Err(format!("unknown redaction: {}", other)),
+        }
+    }
+}
+
+/// Zeroes the host part of an address, keeping its /24 or /48 network.
+pub fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(v6) => {
+            let [a, b, c, ..] = v6.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// First product token of a user agent, e.g. `Mozilla/5.0`.
+pub fn truncate_user_agent(user_agent: &str) -> &str {
+    user_agent.split_whitespace().next().unwrap_or(OMITTED)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    RequestLine,
+    Method,
+    Path,
+    Status,
+    Size,
+    Millis,
+    Seconds,
+    ClientIp,
+    UserAgent,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    parts: Vec<Part>,
+    ip: Redaction,
+    user_agent: Redaction,
+}
+
+impl LogFormat {
+    /// Parses a format string. Unknown placeholders are kept as literal text,
+    /// so no other header can be logged by accident.
+    pub fn new(format: &str, ip: Redaction, user_agent: Redaction) -> Self {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = format;
+        while let Some(pos) = rest.find('%') {
+            text.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            let (part, len) = if rest.starts_with(USER_AGENT_PLACEHOLDER) {
+                (Some(Part::UserAgent), USER_AGENT_PLACEHOLDER.len())
+            } else {
+                let part = match rest.as_bytes().get(1) {
+                    Some(b'r') => Some(Part::RequestLine),
+                    Some(b'm') => Some(Part::Method),
+                    Some(b'U') => Some(Part::Path),
+                    Some(b's') => Some(Part::Status),
+                    Some(b'b') => Some(Part::Size),
+                    Some(b'D') => Some(Part::Millis),
+                    Some(b'T') => Some(Part::Seconds),
+                    Some(b'a') => Some(Part::ClientIp),
+                    _ => None,
+                };
+                (part, 1 + rest[1..].chars().next().map_or(0, char::len_utf8))
+            };
+            match part {
+                Some(part) => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                None => text.push_str(&rest[..len]),
+            }
+            rest = &rest[len..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Self { parts, ip, user_agent }
+    }
+
+    pub fn from_env() -> Self {
+        let redaction = |key: &str, default: &str| {
+            Redaction::from_str(&env::var(key).unwrap_or(default.to_string()))
+                .unwrap_or_else(|e| panic!("{} is invalid: {}", key, e))
+        };
+        Self::new(
+            &env::var(ACCESS_LOG_FORMAT_ENV_KEY).unwrap_or(ACCESS_LOG_FORMAT_DEFAULT.to_string()),
+            redaction(ACCESS_LOG_IP_ENV_KEY, ACCESS_LOG_IP_DEFAULT),
+            redaction(ACCESS_LOG_USER_AGENT_ENV_KEY, ACCESS_LOG_USER_AGENT_DEFAULT),
+        )
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> String {
+        if self.ip == Redaction::Omit {
+            return OMITTED.to_string();
+        }
+        client_ip(req.request()).map_or(OMITTED.to_string(), |ip| truncate_ip(ip).to_string())
+    }
+
+    fn user_agent(&self, req: &ServiceRequest) -> String {
+        if self.user_agent == Redaction::Omit {
+            return OMITTED.to_string();
+        }
+        req.headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| truncate_user_agent(value).to_string())
+            .unwrap_or(OMITTED.to_string())
+    }
+
+    /// Resolves the request fields, leaving the response fields for [`LogEntry::finish`].
+    fn start(&self, req: &ServiceRequest) -> LogEntry {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::RequestLine => Part::Text(format!(
+                    "{} {} {:?}",
+                    req.method(),
+                    req.uri().path_and_query().map_or(req.path(), |pq| pq.as_str()),
+                    req.version()
+                )),
+                Part::Method => Part::Text(req.method().to_string()),
+                Part::Path => Part::Text(req.path().to_string()),
+                Part::ClientIp => Part::Text(self.client_ip(req)),
+                Part::UserAgent => Part::Text(self.user_agent(req)),
+                other => other.clone(),
+            })
+            .collect();
+        LogEntry {
+            parts,
+            started: Instant::now(),
+        }
+    }
+}
+
+struct LogEntry {
+    parts: Vec<Part>,
+    started: Instant,
+}
+
+impl LogEntry {
+    fn finish(self, status: u16, size: BodySize) -> String {
+        let elapsed = self.started.elapsed();
+        self.parts
+            .into_iter()
+            .map(|part| match part {
+                Part::Text(text) => text,
+                Part::Status => status.to_string(),
+                Part::Size => match size {
+                    BodySize::Sized(n) => n.to_string(),
+                    _ => OMITTED.to_string(),
+                },
+                Part::Millis => format!("{:.3}", elapsed.as_secs_f64() * 1000.0),
+                Part::Seconds => format!("{:.6}", elapsed.as_secs_f64()),
+                _ => OMITTED.to_string(),
+            })
+            .collect()
+    }
+}
+
+pub struct AccessLog {
+    format: Rc<LogFormat>,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> Self {
+        Self { format: Rc::new(format) }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(LogFormat::from_env())
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AccessLogService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessLogService {
+            service: Rc::new(service),
+            format: self.format.clone(),
+        }))
+    }
+}
+
+pub struct AccessLogService<S> {
+    service: Rc<S>,
+    format: Rc<LogFormat>,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let entry = self.format.start(&req);
+        let service = self.service.clone();
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let line = entry.finish(res.status().as_u16(), res.response().body().size());
+            log::info!(target: "access_log", "{}", line);
+            Ok(res)
+        })
+    }
+}
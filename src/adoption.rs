@@ -0,0 +1,228 @@
+//! Version adoption: the share of reports sent by each version per day or
+//! week, platform and channel.
+//!
+//! The `top` versions with the most reports in the whole range are reported
+//! on their own for each platform and channel; the remaining ones are summed
+//! up as `other`. Served at `GET /api/v1/reports/adoption` and by the
+//! `adoption` command, as JSON or CSV.
+
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::omaha::compare_versions;
+
+pub const OTHER_VERSION: &str = "other";
+const DEFAULT_TOP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    #[default]
+    Week,
+}
+
+impl Granularity {
+    /// Field name for `date_trunc`.
+    fn field(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+        }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Granularity::Day),
+            "week" => Ok(Granularity::Week),
+            other => Err(format!("unknown granularity: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            other => Err(format!("unknown format: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdoptionQuery {
+    /// First day, inclusive.
+    pub from: NaiveDate,
+    /// Last day, inclusive.
+    pub to: NaiveDate,
+    #[serde(default)]
+    pub granularity: Granularity,
+    pub top: Option<usize>,
+    pub metric_name: Option<String>,
+    pub platform: Option<String>,
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+/// Reports of one version in one period, as counted by the database.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct VersionCount {
+    pub period: NaiveDate,
+    pub platform: String,
+    pub channel: String,
+    pub version: String,
+    pub reports: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdoptionRow {
+    /// First day of the period.
+    pub period: NaiveDate,
+    pub platform: String,
+    pub channel: String,
+    pub version: String,
+    pub reports: i64,
+    /// Share of the period's reports for the platform and channel.
+    pub share: f64,
+}
+
+pub async fn count_versions(pool: &DBPool, query: &AdoptionQuery) -> Result<Vec<VersionCount>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT date_trunc($1, received_at AT TIME ZONE 'UTC')::date AS period,
+               platform, channel, version, count(*) AS reports
+        FROM telemetry_events
+        WHERE received_at >= $2::date::timestamp AT TIME ZONE 'UTC'
+          AND received_at < ($3::date + 1)::timestamp AT TIME ZONE 'UTC'
+          AND ($4::text IS NULL OR metric_name = $4)
+          AND ($5::text IS NULL OR platform = $5)
+          AND ($6::text IS NULL OR channel = $6)
+        GROUP BY 1, 2, 3, 4
+        "#,
+    )
+    .bind(query.granularity.field())
+    .bind(query.from)
+    .bind(query.to)
+    .bind(&query.metric_name)
+    .bind(&query.platform)
+    .bind(&query.channel)
+    .fetch_all(&pool.inner_pool)
+    .await
+}
+
+/// Keeps the `top` versions of each platform and channel, sums the others
+/// into [`OTHER_VERSION`] and computes shares. Rows are ordered by period,
+/// platform, channel and descending reports, with `other` last.
+pub fn build_report(counts: Vec<VersionCount>, top: usize) -> Vec<AdoptionRow> {
+    let mut totals: HashMap<(&str, &str), HashMap<&str, i64>> = HashMap::new();
+    for count in &counts {
+        *totals
+            .entry((&count.platform, &count.channel))
+            .or_default()
+            .entry(&count.version)
+            .or_default() += count.reports;
+    }
+    let kept: HashSet<(String, String, String)> = totals
+        .into_iter()
+        .flat_map(|((platform, channel), versions)| {
+            let mut versions: Vec<(&str, i64)> = versions.into_iter().collect();
+            // Ties go to the newer version.
+            versions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| compare_versions(b.0, a.0)));
+            versions
+                .into_iter()
+                .take(top)
+                .map(move |(version, _)| (platform.to_string(), channel.to_string(), version.to_string()))
+        })
+        .collect();
+
+    let mut periods: BTreeMap<(NaiveDate, String, String), HashMap<String, i64>> = BTreeMap::new();
+    for count in counts {
+        let key = (count.platform.clone(), count.channel.clone(), count.version.clone());
+        let version = if kept.contains(&key) { count.version } else { OTHER_VERSION.to_string() };
+        *periods
+            .entry((count.period, count.platform, count.channel))
+            .or_default()
+            .entry(version)
+            .or_default() += count.reports;
+    }
+
+    let mut rows = Vec::new();
+    for ((period, platform, channel), versions) in periods {
+        let total: i64 = versions.values().sum();
+        let mut versions: Vec<(String, i64)> = versions.into_iter().collect();
+        versions.sort_by_key(|(version, reports)| (version == OTHER_VERSION, Reverse(*reports), version.clone()));
+        rows.extend(versions.into_iter().map(|(version, reports)| AdoptionRow {
+            period,
+            platform: platform.clone(),
+            channel: channel.clone(),
+            version,
+            reports,
+            share: reports as f64 / total as f64,
+        }));
+    }
+    rows
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv(rows: &[AdoptionRow]) -> String {
+    let mut csv = String::from("period,platform,channel,version,reports,share\n");
+    for row in rows {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{:.6}\n",
+            row.period,
+            csv_field(&row.platform),
+            csv_field(&row.channel),
+            csv_field(&row.version),
+            row.reports,
+            row.share
+        ));
+    }
+    csv
+}
+
+pub async fn adoption_report(pool: &DBPool, query: &AdoptionQuery) -> Result<Vec<AdoptionRow>, AppError> {
+    if query.to < query.from {
+        return Err(AppError::BadRequest("to must not be before from".to_string()));
+    }
+    let counts = count_versions(pool, query)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    Ok(build_report(counts, query.top.unwrap_or(DEFAULT_TOP)))
+}
+
+pub async fn get_adoption(pool: web::Data<DBPool>, query: web::Query<AdoptionQuery>) -> Result<HttpResponse, AppError> {
+    let rows = adoption_report(&pool, &query).await?;
+    Ok(match query.format {
+        ReportFormat::Json => HttpResponse::Ok().json(rows),
+        ReportFormat::Csv => HttpResponse::Ok().content_type("text/csv; charset=utf-8").body(to_csv(&rows)),
+    })
+}
@@ -0,0 +1,271 @@
+//! RabbitMQ ingestion transport.
+//!
+//! In AMQP mode the HTTP tier publishes every accepted payload to a durable
+//! exchange instead of buffering it in-process. A separate consumer process
+//! reads the bound queue, batches deliveries into the event sink and acks
+//! them only once the batch is written, so both tiers scale independently.
+//!
+//! When the publisher's connection drops it reconnects on the next publish,
+//! waiting between failed attempts from one second up to thirty. Publishes
+//! fail in the meantime so clients retry.
+
+use futures_util::StreamExt;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    BasicRejectOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions,
+    QueueDeclareOptions,
+};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix::Addr;
+
+use crate::archive::{ArchiveMessage, Archiver};
+use crate::error::AppError;
+use crate::payload::MyPayload;
+use crate::metrics;
+use crate::sink::EventSink;
+use crate::worker::BATCH_SIZE;
+
+const RABBITMQ_URL_ENV_KEY: &str = "RABBITMQ_URL";
+const AMQP_EXCHANGE_ENV_KEY: &str = "AMQP_EXCHANGE";
+const AMQP_EXCHANGE_DEFAULT: &str = "telemetry_events";
+const AMQP_QUEUE_ENV_KEY: &str = "AMQP_QUEUE";
+const AMQP_QUEUE_DEFAULT: &str = "telemetry_events";
+const AMQP_ROUTING_KEY: &str = "event";
+const CONSUMER_TAG: &str = "telemetry_events_consumer";
+const PERSISTENT_DELIVERY_MODE: u8 = 2;
+/// A partially filled batch is flushed after this long without new deliveries.
+const BATCH_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const INSERT_RETRY_DELAY: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct AmqpConfig {
+    pub url: String,
+    pub exchange: String,
+    pub queue: String,
+}
+
+impl AmqpConfig {
+    pub fn from_env() -> Self {
+        Self {
+            url: env::var(RABBITMQ_URL_ENV_KEY)
+                .unwrap_or_else(|_| panic!("{} env var must be defined", RABBITMQ_URL_ENV_KEY)),
+            exchange: env::var(AMQP_EXCHANGE_ENV_KEY)
+                .unwrap_or(AMQP_EXCHANGE_DEFAULT.to_string()),
+            queue: env::var(AMQP_QUEUE_ENV_KEY).unwrap_or(AMQP_QUEUE_DEFAULT.to_string()),
+        }
+    }
+}
+
+/// Declares the durable exchange and queue and binds them, so publisher and
+/// consumer can start in either order.
+async fn open_channel(config: &AmqpConfig) -> Result<(Connection, Channel), lapin::Error> {
+    let connection = Connection::connect(&config.url, ConnectionProperties::default()).await?;
+    let channel = connection.create_channel().await?;
+    channel
+        .exchange_declare(
+            &config.exchange,
+            ExchangeKind::Direct,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .queue_declare(
+            &config.queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .queue_bind(
+            &config.queue,
+            &config.exchange,
+            AMQP_ROUTING_KEY,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+    Ok((connection, channel))
+}
+
+async fn open_publisher_channel(config: &AmqpConfig) -> Result<(Connection, Channel), lapin::Error> {
+    let (connection, channel) = open_channel(config).await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+    Ok((connection, channel))
+}
+
+/// The publisher's connection, and when to try again after it failed.
+struct Link {
+    // The connection is held so it stays open as long as its channel is used.
+    open: Option<(Connection, Channel)>,
+    retry_at: Option<Instant>,
+    delay: Duration,
+}
+
+pub struct AmqpPublisher {
+    config: AmqpConfig,
+    link: tokio::sync::Mutex<Link>,
+}
+
+impl AmqpPublisher {
+    /// Connects once up front, so a misconfigured broker stops startup.
+    pub async fn connect(config: &AmqpConfig) -> Result<Self, lapin::Error> {
+        let open = open_publisher_channel(config).await?;
+        Ok(Self {
+            config: config.clone(),
+            link: tokio::sync::Mutex::new(Link {
+                open: Some(open),
+                retry_at: None,
+                delay: RECONNECT_DELAY_MIN,
+            }),
+        })
+    }
+
+    /// Returns the open channel, reconnecting if it was closed and the
+    /// backoff since the last failed attempt has passed.
+    async fn channel(&self) -> Result<Channel, AppError> {
+        let mut link = self.link.lock().await;
+        if let Some((_, channel)) = &link.open
+            && channel.status().connected()
+        {
+            return Ok(channel.clone());
+        }
+        link.open = None;
+        if link.retry_at.is_some_and(|at| Instant::now() < at) {
+            return Err(AppError::QueueError("broker unavailable".to_string()));
+        }
+        match open_publisher_channel(&self.config).await {
+            Ok((connection, channel)) => {
+                log::info!("Reconnected to RabbitMQ");
+                link.open = Some((connection, channel.clone()));
+                link.retry_at = None;
+                link.delay = RECONNECT_DELAY_MIN;
+                Ok(channel)
+            }
+            Err(e) => {
+                log::error!("Could not reconnect to RabbitMQ, retrying in {:?}: {}", link.delay, e);
+                link.retry_at = Some(Instant::now() + link.delay);
+                link.delay = (link.delay * 2).min(RECONNECT_DELAY_MAX);
+                Err(AppError::QueueError(e.to_string()))
+            }
+        }
+    }
+
+    /// Publishes a persistent message and waits for the broker to confirm it.
+    pub async fn publish(&self, payload: &MyPayload) -> Result<(), AppError> {
+        let body = serde_json::to_vec(payload).map_err(|e| AppError::SerdeError(e.to_string()))?;
+        let confirmation = self
+            .channel()
+            .await?
+            .basic_publish(
+                &self.config.exchange,
+                AMQP_ROUTING_KEY,
+                BasicPublishOptions::default(),
+                &body,
+                BasicProperties::default()
+                    .with_content_type("application/json".into())
+                    .with_delivery_mode(PERSISTENT_DELIVERY_MODE),
+            )
+            .await
+            .map_err(|e| AppError::QueueError(e.to_string()))?
+            .await
+            .map_err(|e| AppError::QueueError(e.to_string()))?;
+        if confirmation.is_nack() {
+            return Err(AppError::QueueError("broker rejected message".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Consumes the queue until the connection closes, writing deliveries to
+/// `sink` in batches of up to `BATCH_SIZE`. Written batches are also handed
+/// to the archiver when one is running.
+pub async fn run_consumer(
+    config: &AmqpConfig,
+    sink: Arc<dyn EventSink>,
+    archive: Option<Addr<Archiver>>,
+) -> Result<(), lapin::Error> {
+    let (_connection, channel) = open_channel(config).await?;
+    channel
+        .basic_qos(BATCH_SIZE as u16, BasicQosOptions::default())
+        .await?;
+    let mut consumer = channel
+        .basic_consume(
+            &config.queue,
+            CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+    log::info!("Consuming events from queue {}", config.queue);
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut last_tag = None;
+    loop {
+        let (idle, closed) = match tokio::time::timeout(BATCH_FLUSH_INTERVAL, consumer.next()).await {
+            Ok(Some(delivery)) => {
+                let delivery = delivery?;
+                match serde_json::from_slice::<MyPayload>(&delivery.data) {
+                    Ok(payload) => {
+                        batch.push(payload);
+                        last_tag = Some(delivery.delivery_tag);
+                    }
+                    Err(e) => {
+                        log::warn!("Dropping undecodable message: {}", e);
+                        channel
+                            .basic_reject(delivery.delivery_tag, BasicRejectOptions { requeue: false })
+                            .await?;
+                    }
+                }
+                (false, false)
+            }
+            Ok(None) => (false, true),
+            Err(_elapsed) => (true, false),
+        };
+
+        // last_tag is only set while the batch holds unacked events.
+        let flush = batch.len() >= BATCH_SIZE || idle || closed;
+        if flush && let Some(tag) = last_tag.take() {
+            let events = std::mem::take(&mut batch);
+            match sink.write(&events).await {
+                Ok(()) => {
+                    metrics::increment("insert_batches_total", &[("result", "ok")]);
+                    if let Some(archive) = &archive {
+                        archive.do_send(ArchiveMessage(events));
+                    }
+                    channel
+                        .basic_ack(tag, BasicAckOptions { multiple: true })
+                        .await?
+                }
+                Err(e) => {
+                    metrics::increment("insert_batches_total", &[("result", "error")]);
+                    log::error!("Failed to write events to {}, requeueing batch: {:?}", sink.name(), e);
+                    channel
+                        .basic_nack(tag, BasicNackOptions { multiple: true, requeue: true })
+                        .await?;
+                    tokio::time::sleep(INSERT_RETRY_DELAY).await;
+                }
+            }
+        }
+
+        if closed {
+            log::info!("Consumer stream closed");
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Raw event archive for replay and reprocessing.
+//!
+//! Accepted payloads are collected by the [`Archiver`] actor and written out
+//! periodically as gzip-compressed NDJSON objects, one per receive date and
+//! channel:
+//!
+//! `<prefix>/date=2025-06-18/channel=release/20250618T101500Z-1a2b3c4d.ndjson.gz`
+//!
+//! [`replay`] reads those objects back through `insert_events`.
+
+use actix::prelude::*;
+use chrono::{NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::future::join_all;
+use futures_util::TryStreamExt;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use std::collections::HashMap;
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::payload::MyPayload;
+use crate::storage::store_from_url;
+use crate::telemetry_event::insert_events;
+use crate::worker::BATCH_SIZE;
+
+const ARCHIVE_URL_ENV_KEY: &str = "ARCHIVE_URL";
+const ARCHIVE_FLUSH_SECS_ENV_KEY: &str = "ARCHIVE_FLUSH_SECS";
+const ARCHIVE_FLUSH_SECS_DEFAULT: &str = "300";
+const ARCHIVE_SUFFIX: &str = ".ndjson.gz";
+
+fn storage_err(e: object_store::Error) -> AppError {
+    AppError::StorageError(e.to_string())
+}
+
+/// Key prefix holding all archives for one date, and optionally one channel.
+pub fn archive_prefix(prefix: &Path, date: NaiveDate, channel: Option<&str>) -> Path {
+    let date_prefix = prefix.child(format!("date={}", date.format("%Y-%m-%d")));
+    match channel {
+        Some(channel) => date_prefix.child(format!("channel={}", channel)),
+        None => date_prefix,
+    }
+}
+
+pub fn encode_batch(events: &[MyPayload]) -> Result<Vec<u8>, AppError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for event in events {
+        serde_json::to_writer(&mut encoder, event).map_err(|e| AppError::SerdeError(e.to_string()))?;
+        encoder.write_all(b"\n")?;
+    }
+    Ok(encoder.finish()?)
+}
+
+pub fn decode_batch(data: &[u8]) -> Result<Vec<MyPayload>, AppError> {
+    let reader = BufReader::new(GzDecoder::new(data));
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        events.push(serde_json::from_str(&line).map_err(|e| AppError::SerdeError(e.to_string()))?);
+    }
+    Ok(events)
+}
+
+/// Writes one archive object and returns its location.
+pub async fn write_batch(
+    store: &dyn ObjectStore,
+    prefix: &Path,
+    date: NaiveDate,
+    channel: &str,
+    events: &[MyPayload],
+) -> Result<Path, AppError> {
+    let location = archive_prefix(prefix, date, Some(channel)).child(format!(
+        "{}-{:08x}{}",
+        Utc::now().format("%Y%m%dT%H%M%SZ"),
+        rand::random::<u32>(),
+        ARCHIVE_SUFFIX
+    ));
+    store
+        .put(&location, encode_batch(events)?.into())
+        .await
+        .map_err(storage_err)?;
+    Ok(location)
+}
+
+/// Lists archive objects under `prefix`, narrowed to a date and channel when given.
+pub async fn list_archives(
+    store: &dyn ObjectStore,
+    prefix: &Path,
+    date: Option<NaiveDate>,
+    channel: Option<&str>,
+) -> Result<Vec<Path>, AppError> {
+    let list_prefix = match date {
+        Some(date) => archive_prefix(prefix, date, channel),
+        None => prefix.clone(),
+    };
+    let channel_segment = channel.map(|channel| format!("channel={}", channel));
+    let mut locations = store
+        .list(Some(&list_prefix))
+        .map_ok(|meta| meta.location)
+        .try_filter(|location| {
+            let keep = location.as_ref().ends_with(ARCHIVE_SUFFIX)
+                && channel_segment
+                    .as_ref()
+                    .is_none_or(|segment| location.parts().any(|part| part.as_ref() == segment));
+            futures_util::future::ready(keep)
+        })
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(storage_err)?;
+    locations.sort();
+    Ok(locations)
+}
+
+pub async fn read_archive(store: &dyn ObjectStore, location: &Path) -> Result<Vec<MyPayload>, AppError> {
+    let data = store
+        .get(location)
+        .await
+        .map_err(storage_err)?
+        .bytes()
+        .await
+        .map_err(storage_err)?;
+    decode_batch(&data)
+}
+
+/// Inserts every matching archived event. Returns the number of replayed events.
+pub async fn replay(
+    archive_url: &str,
+    date: Option<NaiveDate>,
+    channel: Option<&str>,
+    pool: Arc<DBPool>,
+) -> Result<usize, AppError> {
+    let (store, prefix) = store_from_url(archive_url)?;
+    let mut replayed = 0;
+    for location in list_archives(store.as_ref(), &prefix, date, channel).await? {
+        let events = read_archive(store.as_ref(), &location).await?;
+        for chunk in events.chunks(BATCH_SIZE) {
+            insert_events(pool.clone(), chunk.to_vec())
+                .await
+                .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        }
+        log::info!("Replayed {} events from {}", events.len(), location);
+        replayed += events.len();
+    }
+    Ok(replayed)
+}
+
+/// Collects accepted events and writes them to the archive every flush interval.
+pub struct Archiver {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    flush_interval: Duration,
+    pending: HashMap<(NaiveDate, String), Vec<MyPayload>>,
+    uploads: Vec<JoinHandle<()>>,
+}
+
+impl Archiver {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: Path, flush_interval: Duration) -> Self {
+        Self {
+            store,
+            prefix,
+            flush_interval,
+            pending: HashMap::new(),
+            uploads: Vec::new(),
+        }
+    }
+
+    /// Returns an archiver for `ARCHIVE_URL`, or `None` when archiving is disabled.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Some(url) = env::var(ARCHIVE_URL_ENV_KEY).ok().filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        let flush_secs = u64::from_str(
+            &env::var(ARCHIVE_FLUSH_SECS_ENV_KEY).unwrap_or(ARCHIVE_FLUSH_SECS_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|_| panic!("{} must be a positive integer", ARCHIVE_FLUSH_SECS_ENV_KEY));
+        let (store, prefix) = store_from_url(&url)?;
+        Ok(Some(Self::new(store, prefix, Duration::from_secs(flush_secs))))
+    }
+
+    fn flush(&mut self) {
+        self.uploads.retain(|upload| !upload.is_finished());
+        for ((date, channel), events) in self.pending.drain() {
+            let store = self.store.clone();
+            let prefix = self.prefix.clone();
+            self.uploads.push(actix::spawn(async move {
+                match write_batch(store.as_ref(), &prefix, date, &channel, &events).await {
+                    Ok(location) => log::debug!("Archived {} events to {}", events.len(), location),
+                    Err(e) => log::error!("Failed to archive {} events: {}", events.len(), e),
+                }
+            }));
+        }
+    }
+}
+
+/// Writes out pending events and resolves once every upload, including
+/// those started by earlier interval flushes, has finished. Sent before
+/// shutdown, as uploads still running when the system stops are lost.
+pub struct FlushArchive;
+
+impl actix::Message for FlushArchive {
+    type Result = ();
+}
+
+impl Handler<FlushArchive> for Archiver {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _msg: FlushArchive, _ctx: &mut Self::Context) -> Self::Result {
+        self.flush();
+        let uploads = std::mem::take(&mut self.uploads);
+        Box::pin(async move {
+            join_all(uploads).await;
+        })
+    }
+}
+
+pub struct ArchiveMessage(pub Vec<MyPayload>);
+
+impl actix::Message for ArchiveMessage {
+    type Result = ();
+}
+
+impl Handler<ArchiveMessage> for Archiver {
+    type Result = ();
+
+    fn handle(&mut self, msg: ArchiveMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let date = Utc::now().date_naive();
+        for event in msg.0 {
+            self.pending
+                .entry((date, event.channel.clone()))
+                .or_default()
+                .push(event);
+        }
+    }
+}
+
+impl Actor for Archiver {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.flush_interval, |archiver, _ctx| archiver.flush());
+    }
+
+    /// Best effort only, the uploads are not awaited: send [`FlushArchive`]
+    /// before stopping the system.
+    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+        self.flush();
+        Running::Stop
+    }
+}
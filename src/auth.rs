@@ -0,0 +1,111 @@
+//! Service key authentication for administrative routes.
+//!
+//! Requests must carry the key configured in `BRAVE_SERVICE_KEY` in the
+//! `BraveServiceKey` header, otherwise they are answered with 401. When no key
+//! is configured every request is rejected.
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use std::env;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+const SERVICE_KEY_ENV_KEY: &str = "BRAVE_SERVICE_KEY";
+pub const SERVICE_KEY_HEADER: &str = "BraveServiceKey";
+
+/// Compares without short-circuiting so the key can't be guessed byte by
+/// byte from response timings.
+fn keys_match(expected: &[u8], provided: &[u8]) -> bool {
+    !expected.is_empty()
+        && expected.len() == provided.len()
+        && expected
+            .iter()
+            .zip(provided)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+pub(crate) fn service_key_from_env() -> Option<String> {
+    env::var(SERVICE_KEY_ENV_KEY).ok().filter(|v| !v.is_empty())
+}
+
+/// Whether `req` carries `service_key` in the service key header.
+pub(crate) fn has_service_key(req: &HttpRequest, service_key: &str) -> bool {
+    req.headers()
+        .get(SERVICE_KEY_HEADER)
+        .is_some_and(|value| keys_match(service_key.as_bytes(), value.as_bytes()))
+}
+
+pub struct AuthMiddleware {
+    service_key: Rc<String>,
+}
+
+impl AuthMiddleware {
+    pub fn new() -> Self {
+        let service_key = service_key_from_env().unwrap_or_else(|| {
+            log::warn!("{} is not set, authenticated routes will reject all requests", SERVICE_KEY_ENV_KEY);
+            String::new()
+        });
+        Self::with_key(service_key)
+    }
+
+    pub fn with_key(service_key: impl Into<String>) -> Self {
+        Self {
+            service_key: Rc::new(service_key.into()),
+        }
+    }
+}
+
+impl Default for AuthMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthService {
+            service: Rc::new(service),
+            service_key: self.service_key.clone(),
+        }))
+    }
+}
+
+pub struct AuthService<S> {
+    service: Rc<S>,
+    service_key: Rc<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if !has_service_key(req.request(), &self.service_key) {
+            let response = HttpResponse::Unauthorized().body("Invalid service key");
+            return Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) });
+        }
+
+        let service = self.service.clone();
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
@@ -0,0 +1,96 @@
+//! Client addresses of requests behind reverse proxies.
+//!
+//! `X-Forwarded-For` is only believed when the connecting peer is listed in
+//! `TRUSTED_PROXIES`, a comma separated list of addresses. The client is then
+//! the right-most forwarded address that isn't a trusted proxy, so addresses
+//! the client prepended itself are ignored. Otherwise the peer address is used.
+//!
+//! Where an address has to be kept as a key, [`ip_digest`] hashes it with a
+//! secret generated at startup, so it can't be recovered by hashing the
+//! address space.
+
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+const TRUSTED_PROXIES_ENV_KEY: &str = "TRUSTED_PROXIES";
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+fn parse_addr(value: &str) -> Option<IpAddr> {
+    value
+        .parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| value.parse::<IpAddr>())
+        .ok()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrustedProxies(HashSet<IpAddr>);
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+            .map(|addr| IpAddr::from_str(addr).map_err(|_| format!("invalid proxy address: {}", addr)))
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies)
+    }
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> &'static Self {
+        static PROXIES: OnceLock<TrustedProxies> = OnceLock::new();
+        PROXIES.get_or_init(|| {
+            TrustedProxies::from_str(&env::var(TRUSTED_PROXIES_ENV_KEY).unwrap_or_default())
+                .unwrap_or_else(|e| panic!("{} is invalid: {}", TRUSTED_PROXIES_ENV_KEY, e))
+        })
+    }
+
+    /// The address of the client that sent `req`, if the peer is known.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = req.peer_addr()?.ip();
+        if !self.0.contains(&client) {
+            return Some(client);
+        }
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all(FORWARDED_FOR_HEADER)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in forwarded.into_iter().rev() {
+            let Some(addr) = parse_addr(hop) else {
+                break;
+            };
+            client = addr;
+            if !self.0.contains(&client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// [`TrustedProxies::client_ip`] with the proxies from `TRUSTED_PROXIES`.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    TrustedProxies::from_env().client_ip(req)
+}
+
+/// A truncated SHA-256 of the per-process secret and `ip`.
+pub fn ip_digest(ip: Option<IpAddr>) -> String {
+    static SECRET: OnceLock<[u8; 32]> = OnceLock::new();
+    let addr = ip.map_or("unknown".to_string(), |ip| ip.to_string());
+    let digest = Sha256::new()
+        .chain_update(SECRET.get_or_init(rand::random))
+        .chain_update(addr.as_bytes())
+        .finalize();
+    format!("{:x}", digest)[..32].to_string()
+}
@@ -0,0 +1,89 @@
+//! In-memory store of recently seen idempotency keys.
+//!
+//! Retried submissions carrying the same key within the window are dropped
+//! before they reach the queue. The store is bounded, so under pressure the
+//! oldest keys are forgotten first and duplicates fall through to the
+//! `telemetry_event_keys` check in `insert_events`.
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEDUP_WINDOW_SECS_ENV_KEY: &str = "DEDUP_WINDOW_SECS";
+const DEDUP_WINDOW_SECS_DEFAULT: &str = "3600";
+const DEDUP_CAPACITY_ENV_KEY: &str = "DEDUP_CAPACITY";
+const DEDUP_CAPACITY_DEFAULT: &str = "100000";
+
+/// Longest idempotency key accepted, in bytes.
+pub const MAX_KEY_LEN: usize = 255;
+
+#[derive(Default)]
+struct DedupState {
+    seen: HashMap<String, Instant>,
+    order: VecDeque<(Instant, String)>,
+}
+
+pub struct DedupStore {
+    window: Duration,
+    capacity: usize,
+    state: Mutex<DedupState>,
+}
+
+impl DedupStore {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity,
+            state: Mutex::new(DedupState::default()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let window_secs = u64::from_str(
+            &env::var(DEDUP_WINDOW_SECS_ENV_KEY).unwrap_or(DEDUP_WINDOW_SECS_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|_| panic!("{} must be a positive integer", DEDUP_WINDOW_SECS_ENV_KEY));
+        let capacity = usize::from_str(
+            &env::var(DEDUP_CAPACITY_ENV_KEY).unwrap_or(DEDUP_CAPACITY_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|_| panic!("{} must be a positive integer", DEDUP_CAPACITY_ENV_KEY));
+        Self::new(Duration::from_secs(window_secs), capacity)
+    }
+
+    /// Remembers `key` and returns `true` if it was not seen within the window.
+    pub fn insert(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        while let Some((seen_at, _)) = state.order.front() {
+            if now.duration_since(*seen_at) < self.window && state.order.len() < self.capacity {
+                break;
+            }
+            let (_, old_key) = state.order.pop_front().unwrap();
+            state.seen.remove(&old_key);
+        }
+        if state.seen.contains_key(key) {
+            return false;
+        }
+        state.seen.insert(key.to_string(), now);
+        state.order.push_back((now, key.to_string()));
+        true
+    }
+
+    /// Forgets `key`, so a retry after a failed enqueue is accepted.
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.seen.remove(key).is_some() {
+            state.order.retain(|(_, old_key)| old_key != key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
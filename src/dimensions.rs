@@ -0,0 +1,233 @@
+//! Dimension tables for the low-cardinality text columns of telemetry events.
+//!
+//! Each value is stored once in `telemetry_dim_<column>` and referenced by id
+//! from `telemetry_event_facts`. The writer keeps the ids it has seen in
+//! memory, so a batch only touches the dimension tables for new values.
+//! New values are created in the batch's transaction and only cached once it
+//! commits, so a failed batch leaves neither rows nor stale ids behind.
+
+use lru::LruCache;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use sqlx::PgConnection;
+
+use crate::payload::MyPayload;
+use crate::version::Version;
+
+/// Entries kept per dimension; the least recently used one is dropped beyond this.
+const MAX_CACHED_VALUES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Cadence,
+    Channel,
+    CountryCode,
+    MetricName,
+    Platform,
+    Version,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 6] = [
+        Dimension::Cadence,
+        Dimension::Channel,
+        Dimension::CountryCode,
+        Dimension::MetricName,
+        Dimension::Platform,
+        Dimension::Version,
+    ];
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            Dimension::Cadence => "telemetry_dim_cadence",
+            Dimension::Channel => "telemetry_dim_channel",
+            Dimension::CountryCode => "telemetry_dim_country_code",
+            Dimension::MetricName => "telemetry_dim_metric_name",
+            Dimension::Platform => "telemetry_dim_platform",
+            Dimension::Version => "telemetry_dim_version",
+        }
+    }
+
+    pub fn value<'a>(&self, event: &'a MyPayload) -> &'a str {
+        match self {
+            Dimension::Cadence => &event.cadence,
+            Dimension::Channel => &event.channel,
+            Dimension::CountryCode => &event.country_code,
+            Dimension::MetricName => &event.metric_name,
+            Dimension::Platform => &event.platform,
+            Dimension::Version => &event.version,
+        }
+    }
+}
+
+/// Dimension ids of one event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DimensionIds {
+    pub cadence: i32,
+    pub channel: i32,
+    pub country_code: i32,
+    pub metric_name: i32,
+    pub platform: i32,
+    pub version: i32,
+}
+
+impl DimensionIds {
+    fn set(&mut self, dimension: Dimension, id: i32) {
+        match dimension {
+            Dimension::Cadence => self.cadence = id,
+            Dimension::Channel => self.channel = id,
+            Dimension::CountryCode => self.country_code = id,
+            Dimension::MetricName => self.metric_name = id,
+            Dimension::Platform => self.platform = id,
+            Dimension::Version => self.version = id,
+        }
+    }
+}
+
+/// Dimension ids of a batch, from [`DimensionCache::resolve`].
+#[derive(Debug, Default)]
+pub struct Resolved {
+    pub ids: Vec<DimensionIds>,
+    /// Ids that weren't cached yet, for [`DimensionCache::remember`].
+    fetched: Vec<(Dimension, Vec<(i32, String)>)>,
+}
+
+#[derive(Default)]
+pub struct DimensionCache {
+    ids: Mutex<HashMap<Dimension, LruCache<String, i32>>>,
+}
+
+impl DimensionCache {
+    pub fn cached(&self, dimension: Dimension, value: &str) -> Option<i32> {
+        self.ids.lock().unwrap().get_mut(&dimension)?.get(value).copied()
+    }
+
+    /// Caches the ids fetched for a batch. Call once its transaction has
+    /// committed.
+    pub fn remember(&self, resolved: Resolved) {
+        let mut ids = self.ids.lock().unwrap();
+        for (dimension, rows) in resolved.fetched {
+            let values = ids
+                .entry(dimension)
+                .or_insert_with(|| LruCache::new(NonZeroUsize::new(MAX_CACHED_VALUES).unwrap()));
+            for (id, value) in rows {
+                values.put(value, id);
+            }
+        }
+    }
+
+    /// Creates the distinct `values` of `dimension` that don't exist yet, new
+    /// versions with their parsed components, and returns their ids.
+    async fn fetch(
+        &self,
+        conn: &mut PgConnection,
+        dimension: Dimension,
+        values: Vec<&str>,
+    ) -> Result<Vec<(i32, String)>, sqlx::Error> {
+        // The table name comes from the enum, never from input.
+        let table = dimension.table();
+        let mut rows: Vec<(i32, String)> = if dimension == Dimension::Version {
+            let parsed: Vec<Option<Version>> = values.iter().map(|value| Version::parse(value)).collect();
+            let component = |f: fn(&Version) -> Option<i32>| -> Vec<Option<i32>> {
+                parsed.iter().map(|v| v.as_ref().and_then(f)).collect()
+            };
+            sqlx::query_as(&format!(
+                r#"
+                WITH input AS (
+                    SELECT * FROM UNNEST($1::text[], $2::int4[], $3::int4[], $4::int4[], $5::int4[])
+                        AS t (value, major, minor, build, patch)
+                ),
+                created AS (
+                    INSERT INTO {table} (value, major, minor, build, patch)
+                    SELECT value, major, minor, build, patch FROM input
+                    ON CONFLICT (value) DO NOTHING
+                    RETURNING id, value
+                )
+                SELECT id, value FROM created
+                UNION ALL
+                SELECT d.id, d.value FROM {table} d JOIN input USING (value)
+                "#
+            ))
+            .bind(&values)
+            .bind(component(|v| Some(v.major)))
+            .bind(component(|v| v.minor))
+            .bind(component(|v| v.build))
+            .bind(component(|v| v.patch))
+            .fetch_all(&mut *conn)
+            .await?
+        } else {
+            sqlx::query_as(&format!(
+                r#"
+                WITH input AS (
+                    SELECT DISTINCT value FROM UNNEST($1::text[]) AS t (value)
+                ),
+                created AS (
+                    INSERT INTO {table} (value)
+                    SELECT value FROM input
+                    ON CONFLICT (value) DO NOTHING
+                    RETURNING id, value
+                )
+                SELECT id, value FROM created
+                UNION ALL
+                SELECT d.id, d.value FROM {table} d JOIN input USING (value)
+                "#
+            ))
+            .bind(&values)
+            .fetch_all(&mut *conn)
+            .await?
+        };
+        let found: HashSet<&str> = rows.iter().map(|(_, value)| value.as_str()).collect();
+        // A value committed by another writer after this statement's snapshot
+        // is neither created nor visible, so look it up again.
+        let missed: Vec<&str> = values.into_iter().filter(|value| !found.contains(value)).collect();
+        if !missed.is_empty() {
+            rows.extend(
+                sqlx::query_as::<_, (i32, String)>(&format!("SELECT id, value FROM {table} WHERE value = ANY($1)"))
+                    .bind(&missed)
+                    .fetch_all(&mut *conn)
+                    .await?,
+            );
+        }
+        Ok(rows)
+    }
+
+    /// Returns the dimension ids of every event, in order, creating missing
+    /// values through `conn`, normally the batch's transaction.
+    pub async fn resolve(&self, conn: &mut PgConnection, events: &[MyPayload]) -> Result<Resolved, sqlx::Error> {
+        let mut resolved = Resolved {
+            ids: vec![DimensionIds::default(); events.len()],
+            fetched: Vec::new(),
+        };
+        for dimension in Dimension::ALL {
+            let mut known: HashMap<&str, i32> = HashMap::new();
+            let mut missing: Vec<&str> = Vec::new();
+            for event in events {
+                let value = dimension.value(event);
+                match self.cached(dimension, value) {
+                    Some(id) => {
+                        known.insert(value, id);
+                    }
+                    None => missing.push(value),
+                }
+            }
+            missing.sort_unstable();
+            missing.dedup();
+            let fetched = if missing.is_empty() {
+                Vec::new()
+            } else {
+                self.fetch(conn, dimension, missing).await?
+            };
+            known.extend(fetched.iter().map(|(id, value)| (value.as_str(), *id)));
+            for (event, ids) in events.iter().zip(resolved.ids.iter_mut()) {
+                let id = known.get(dimension.value(event)).ok_or(sqlx::Error::RowNotFound)?;
+                ids.set(dimension, *id);
+            }
+            if !fetched.is_empty() {
+                resolved.fetched.push((dimension, fetched));
+            }
+        }
+        Ok(resolved)
+    }
+}
@@ -7,6 +7,9 @@ pub enum AppError {
     #[error("BadRequest error: {0}")]
     BadRequest(String),
     
+    #[error("NotFound error: {0}")]
+    NotFound(String),
+
     #[error("Invalid ID provided")]
     InvalidId,
 
@@ -16,6 +19,12 @@ pub enum AppError {
     #[error("SerdeError error: {0}")]
     SerdeError(String),
 
+    #[error("QueueError error: {0}")]
+    QueueError(String),
+
+    #[error("StorageError error: {0}")]
+    StorageError(String),
+
     #[error("InternalError error: {0}")]
     InternalError(#[from] std::io::Error),
 
@@ -27,8 +36,12 @@ impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         match self {
             AppError::BadRequest(_) => {
-                HttpResponse::InternalServerError().body(self.to_string())
-            },AppError::InvalidId => {
+                HttpResponse::BadRequest().body(self.to_string())
+            },
+            AppError::NotFound(_) => {
+                HttpResponse::NotFound().body(self.to_string())
+            },
+            AppError::InvalidId => {
                 HttpResponse::InternalServerError().body(self.to_string())
             },
             AppError::DatabaseError(_) => {
@@ -37,6 +50,12 @@ impl ResponseError for AppError {
             AppError::SerdeError(_) => {
                 HttpResponse::InternalServerError().body(self.to_string())
             },
+            AppError::QueueError(_) => {
+                HttpResponse::InternalServerError().body(self.to_string())
+            },
+            AppError::StorageError(_) => {
+                HttpResponse::InternalServerError().body(self.to_string())
+            },
             AppError::InternalError(_) => {
                 HttpResponse::InternalServerError().body(self.to_string())
             },
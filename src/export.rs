@@ -0,0 +1,290 @@
+//! Offline extracts of `telemetry_events` as Parquet or CSV files.
+//!
+//! Rows are read in chunks of `chunk_size`, ordered by id, and each chunk is
+//! written out before the next one is fetched: a Parquet row group or a run
+//! of CSV lines. Memory use depends on the chunk size, not on the export.
+//! The finished file can be uploaded to an `s3://` or `file://` location.
+
+use arrow_array::{
+    ArrayRef, Int16Array, Int32Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, NaiveDate, Utc};
+use object_store::WriteMultipart;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::storage::store_from_url;
+
+pub const DEFAULT_CHUNK_SIZE: i64 = 50_000;
+const UPLOAD_PART_SIZE: usize = 8 * 1024 * 1024;
+const UPLOAD_MAX_PENDING_PARTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parquet" => Ok(ExportFormat::Parquet),
+            "csv" => Ok(ExportFormat::Csv),
+            other => Err(format!("unknown export format: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// First day, inclusive.
+    pub from: NaiveDate,
+    /// Last day, inclusive.
+    pub to: NaiveDate,
+    pub channel: Option<String>,
+    /// Metric name, or a prefix ending in `*` such as `Brave.Today.*`.
+    pub metric: Option<String>,
+    pub format: ExportFormat,
+    pub chunk_size: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct ExportRow {
+    pub id: i64,
+    pub received_at: DateTime<Utc>,
+    pub cadence: String,
+    pub channel: String,
+    pub country_code: String,
+    pub metric_name: String,
+    pub metric_value: i32,
+    pub platform: String,
+    pub version: String,
+    pub woi: Option<i16>,
+    pub wos: Option<i16>,
+    pub yoi: Option<i16>,
+    pub yos: i16,
+    /// Extra payload fields as a JSON object.
+    pub extra: Option<String>,
+}
+
+pub fn schema() -> SchemaRef {
+    let text = |name: &str| Field::new(name, DataType::Utf8, false);
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("received_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+        text("cadence"),
+        text("channel"),
+        text("country_code"),
+        text("metric_name"),
+        Field::new("metric_value", DataType::Int32, false),
+        text("platform"),
+        text("version"),
+        Field::new("woi", DataType::Int16, true),
+        Field::new("wos", DataType::Int16, true),
+        Field::new("yoi", DataType::Int16, true),
+        Field::new("yos", DataType::Int16, false),
+        Field::new("extra", DataType::Utf8, true),
+    ]))
+}
+
+pub fn record_batch(schema: SchemaRef, rows: &[ExportRow]) -> Result<RecordBatch, AppError> {
+    let text = |f: fn(&ExportRow) -> &str| -> ArrayRef { Arc::new(StringArray::from_iter_values(rows.iter().map(f))) };
+    let small = |f: fn(&ExportRow) -> i16| -> ArrayRef { Arc::new(Int16Array::from_iter_values(rows.iter().map(f))) };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.id))),
+        Arc::new(
+            TimestampMicrosecondArray::from_iter_values(rows.iter().map(|r| r.received_at.timestamp_micros()))
+                .with_timezone("UTC"),
+        ),
+        text(|r| &r.cadence),
+        text(|r| &r.channel),
+        text(|r| &r.country_code),
+        text(|r| &r.metric_name),
+        Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.metric_value))),
+        text(|r| &r.platform),
+        text(|r| &r.version),
+        Arc::new(rows.iter().map(|r| r.woi).collect::<Int16Array>()),
+        Arc::new(rows.iter().map(|r| r.wos).collect::<Int16Array>()),
+        Arc::new(rows.iter().map(|r| r.yoi).collect::<Int16Array>()),
+        small(|r| r.yos),
+        Arc::new(rows.iter().map(|r| r.extra.as_deref()).collect::<StringArray>()),
+    ];
+    RecordBatch::try_new(schema, columns).map_err(|e| AppError::SerdeError(e.to_string()))
+}
+
+enum ExportWriter {
+    Csv(Box<csv::Writer<File>>),
+    Parquet(Box<ArrowWriter<File>>, SchemaRef),
+}
+
+impl ExportWriter {
+    fn create(format: ExportFormat, path: &Path) -> Result<Self, AppError> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ExportFormat::Csv => ExportWriter::Csv(Box::new(csv::Writer::from_writer(file))),
+            ExportFormat::Parquet => {
+                let schema = schema();
+                let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+                let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+                    .map_err(|e| AppError::SerdeError(e.to_string()))?;
+                ExportWriter::Parquet(Box::new(writer), schema)
+            }
+        })
+    }
+
+    fn write(&mut self, rows: &[ExportRow]) -> Result<(), AppError> {
+        let serde_error = |e: &dyn std::fmt::Display| AppError::SerdeError(e.to_string());
+        match self {
+            ExportWriter::Csv(writer) => {
+                for row in rows {
+                    writer.serialize(row).map_err(|e| serde_error(&e))?;
+                }
+                writer.flush()?;
+            }
+            ExportWriter::Parquet(writer, schema) => {
+                writer
+                    .write(&record_batch(schema.clone(), rows)?)
+                    .map_err(|e| serde_error(&e))?;
+                // Ends the row group, so buffered rows never exceed a chunk.
+                writer.flush().map_err(|e| serde_error(&e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), AppError> {
+        match self {
+            ExportWriter::Csv(mut writer) => writer.flush()?,
+            ExportWriter::Parquet(writer, _) => {
+                writer.close().map_err(|e| AppError::SerdeError(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escapes `LIKE` wildcards so a prefix matches literally.
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+async fn fetch_chunk(pool: &DBPool, options: &ExportOptions, after_id: i64) -> Result<Vec<ExportRow>, sqlx::Error> {
+    let (metric_name, metric_pattern) = match options.metric.as_deref() {
+        Some(metric) => match metric.strip_suffix('*') {
+            Some(prefix) => (None, Some(like_prefix(prefix))),
+            None => (Some(metric), None),
+        },
+        None => (None, None),
+    };
+    sqlx::query_as(
+        r#"
+        SELECT id, received_at, cadence, channel, country_code, metric_name, metric_value,
+               platform, version, woi, wos, yoi, yos, extra::text AS extra
+        FROM telemetry_events
+        WHERE received_at >= $1::date::timestamp AT TIME ZONE 'UTC'
+          AND received_at < ($2::date + 1)::timestamp AT TIME ZONE 'UTC'
+          AND ($3::text IS NULL OR channel = $3)
+          AND ($4::text IS NULL OR metric_name = $4)
+          AND ($5::text IS NULL OR metric_name LIKE $5)
+          AND id > $6
+        ORDER BY id
+        LIMIT $7
+        "#,
+    )
+    .bind(options.from)
+    .bind(options.to)
+    .bind(&options.channel)
+    .bind(metric_name)
+    .bind(metric_pattern)
+    .bind(after_id)
+    .bind(options.chunk_size)
+    .fetch_all(&pool.inner_pool)
+    .await
+}
+
+/// Writes the matching rows to `path` and returns how many were written.
+pub async fn export(pool: &DBPool, options: &ExportOptions, path: &Path) -> Result<u64, AppError> {
+    if options.to < options.from {
+        return Err(AppError::BadRequest("to must not be before from".to_string()));
+    }
+    if options.chunk_size < 1 {
+        return Err(AppError::BadRequest("chunk size must be positive".to_string()));
+    }
+    let mut writer = ExportWriter::create(options.format, path)?;
+    let mut after_id = 0;
+    let mut written = 0;
+    loop {
+        let rows = fetch_chunk(pool, options, after_id)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        after_id = last.id;
+        writer.write(&rows)?;
+        written += rows.len() as u64;
+        log::info!("Exported {} rows", written);
+        if (rows.len() as i64) < options.chunk_size {
+            break;
+        }
+    }
+    writer.finish()?;
+    Ok(written)
+}
+
+/// Uploads `path` under the prefix of `url`, keeping its file name, in
+/// parts so large exports aren't read into memory at once.
+pub async fn upload(path: &Path, url: &str) -> Result<object_store::path::Path, AppError> {
+    let storage_error = |e: object_store::Error| AppError::StorageError(e.to_string());
+    let (store, prefix) = store_from_url(url)?;
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| AppError::BadRequest(format!("invalid export path: {}", path.display())))?;
+    let location = prefix.child(name);
+    let mut upload = WriteMultipart::new_with_chunk_size(
+        store.put_multipart(&location).await.map_err(storage_error)?,
+        UPLOAD_PART_SIZE,
+    );
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; UPLOAD_PART_SIZE];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        upload.wait_for_capacity(UPLOAD_MAX_PENDING_PARTS).await.map_err(storage_error)?;
+        upload.write(&buf[..read]);
+    }
+    upload.finish().await.map_err(storage_error)?;
+    Ok(location)
+}
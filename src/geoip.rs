@@ -0,0 +1,108 @@
+//! Server-side country derivation from a local MaxMind-format database.
+//!
+//! Enabled by pointing `GEOIP_DB_PATH` at a GeoIP2/GeoLite2 Country (or City)
+//! file; lookups never leave the process. The client address, as resolved by
+//! [`client_ip`](crate::client_ip::client_ip), is only used for the lookup
+//! and is not stored. `GEOIP_COUNTRY_POLICY` decides between the client's
+//! `country_code` and the derived one:
+//!
+//! - `prefer_client` (default): keep the client value, fill it in when empty
+//! - `prefer_server`: replace the client value whenever the lookup succeeds
+//! - `validate`: reject submissions whose country disagrees with the lookup
+
+use maxminddb::{geoip2, Reader};
+use std::env;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::AppError;
+use crate::metrics;
+use crate::payload::MyPayload;
+
+const GEOIP_DB_PATH_ENV_KEY: &str = "GEOIP_DB_PATH";
+const GEOIP_COUNTRY_POLICY_ENV_KEY: &str = "GEOIP_COUNTRY_POLICY";
+const GEOIP_COUNTRY_POLICY_DEFAULT: &str = "prefer_client";
+/// Country codes clients send when they do not know theirs.
+const UNKNOWN_COUNTRIES: [&str; 2] = ["", "--"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountryPolicy {
+    PreferClient,
+    PreferServer,
+    Validate,
+}
+
+impl FromStr for CountryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer_client" => Ok(CountryPolicy::PreferClient),
+            "prefer_server" => Ok(CountryPolicy::PreferServer),
+            "validate" => Ok(CountryPolicy::Validate),
+            other => Err(format!("unknown country policy: {}", other)),
+        }
+    }
+}
+
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+    policy: CountryPolicy,
+}
+
+impl GeoIp {
+    pub fn open(path: &Path, policy: CountryPolicy) -> Result<Self, AppError> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| AppError::StorageError(format!("{}: {}", path.display(), e)))?;
+        Ok(Self { reader, policy })
+    }
+
+    /// Returns `None` when `GEOIP_DB_PATH` is unset, leaving countries as sent.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Some(path) = env::var(GEOIP_DB_PATH_ENV_KEY).ok().filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        let policy = CountryPolicy::from_str(
+            &env::var(GEOIP_COUNTRY_POLICY_ENV_KEY).unwrap_or(GEOIP_COUNTRY_POLICY_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|e| panic!("{} is invalid: {}", GEOIP_COUNTRY_POLICY_ENV_KEY, e));
+        Self::open(Path::new(&path), policy).map(Some)
+    }
+
+    /// Upper-case ISO 3166 code of the country `ip` is located in.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.reader.lookup(ip).ok()?;
+        record
+            .country
+            .and_then(|country| country.iso_code)
+            .map(str::to_uppercase)
+    }
+
+    /// Applies the policy to `payload` using the connecting address.
+    pub fn apply(&self, payload: &mut MyPayload, ip: Option<IpAddr>) -> Result<(), AppError> {
+        let Some(server) = ip.and_then(|ip| self.country(ip)) else {
+            metrics::increment("geoip_lookups_total", &[("outcome", "not_found")]);
+            return Ok(());
+        };
+        let client_unknown = UNKNOWN_COUNTRIES.contains(&payload.country_code.as_str());
+        let matches = payload.country_code.eq_ignore_ascii_case(&server);
+        metrics::increment(
+            "geoip_lookups_total",
+            &[("outcome", if client_unknown { "filled" } else if matches { "match" } else { "mismatch" })],
+        );
+        match self.policy {
+            _ if client_unknown => payload.country_code = server,
+            CountryPolicy::PreferClient => {}
+            CountryPolicy::PreferServer => payload.country_code = server,
+            CountryPolicy::Validate if !matches => {
+                return Err(AppError::BadRequest(format!(
+                    "country_code {} does not match the connecting address",
+                    payload.country_code
+                )));
+            }
+            CountryPolicy::Validate => {}
+        }
+        Ok(())
+    }
+}
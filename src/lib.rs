@@ -5,7 +5,31 @@ pub mod queue_job;
 pub mod error;
 pub mod routers;
 pub mod models;
+pub mod wal;
+pub mod amqp;
+pub mod partitions;
+pub mod storage;
+pub mod archive;
+pub mod omaha;
+pub mod auth;
+pub mod access_log;
+pub mod dedup;
+pub mod metrics;
+pub mod ratelimit;
+pub mod geoip;
+pub mod registry;
+pub mod quarantine;
+pub mod dimensions;
+pub mod version;
+pub mod query;
+pub mod temporal;
+pub mod retention;
+pub mod adoption;
+pub mod export;
+pub mod sink;
+pub mod client_ip;
 mod channel;
+#[allow(dead_code)]
 mod profiler;
 
 
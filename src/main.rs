@@ -1,54 +1,392 @@
 use actix_web::{web, App, HttpServer};
-use actix_web::middleware::Logger;
+use std::path::PathBuf;
 use std::sync::Arc;
-use actix::Actor;
-use clap::Parser;
+use actix::{Actor, Addr};
+use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use telemetry_events::adoption::{self, AdoptionQuery, Granularity, ReportFormat};
+use telemetry_events::amqp::{self, AmqpConfig, AmqpPublisher};
+use telemetry_events::access_log::AccessLog;
+use telemetry_events::archive::{self, Archiver, FlushArchive};
+use telemetry_events::dedup::DedupStore;
+use telemetry_events::export::{self, ExportFormat, ExportOptions};
+use telemetry_events::geoip::GeoIp;
+use telemetry_events::registry::MetricRegistry;
+use telemetry_events::temporal::TemporalPolicy;
+use telemetry_events::retention::RetentionRefresher;
+use telemetry_events::sink::{self, PostgresSink, SinkConfig};
+use telemetry_events::metrics;
 use telemetry_events::models::{DBConnectionType, DBPool};
+use telemetry_events::omaha::packages::{self, PackageStore};
+use telemetry_events::omaha::{self, import, rollout, Catalog};
+use telemetry_events::partitions;
+use telemetry_events::queue_job::EventQueue;
 use telemetry_events::worker::ActorWorker;
-use telemetry_events::routers::service_scope;
+use telemetry_events::routers::{crx_scope, service_scope, update_scope};
+use telemetry_events::wal::{self, FsyncPolicy, Wal};
+
 #[derive(Parser, Debug, Clone)]
 #[clap(version, about)]
-
 struct CliArgs {
-    #[clap(short, long, help = "Enable server mode")]
-    server: bool,
-    
     #[clap(
         short = 'c',
         long,
+        global = true,
         default_value = "p3a_db",
         help = "Main data channel to use. See README for details on data channel configuration."
     )]
     main_channel_name: String,
+
+    #[clap(subcommand)]
+    command: Command,
 }
-#[actix::main]
-async fn main() -> std::io::Result<()> {
-    dotenvy::dotenv().ok();
-    env_logger::init();
-    let cli_args = CliArgs::parse();
-    let channel_name = DBConnectionType::Normal { channel_name: &*cli_args.main_channel_name.clone() };
-    let db_pool = Arc::new(DBPool::new(channel_name).await);
- 
-    let worker_addr =  ActorWorker {
-        pool: db_pool.clone(),
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Run the HTTP front end that accepts submissions
+    Server {
+        #[clap(long, default_value = "0.0.0.0:8011", help = "Address to listen on")]
+        bind: String,
+
+        #[clap(long, help = "Publish accepted events to RabbitMQ instead of the in-process worker")]
+        amqp: bool,
+    },
+    /// Run the RabbitMQ consumer that writes queued events to the database
+    Worker,
+    /// Apply pending database migrations
+    Migrate,
+    /// Insert events left in WAL segments, or archived events, into the database
+    Replay {
+        #[clap(long, help = "WAL directory to replay. Defaults to WAL_DIR.")]
+        wal_dir: Option<PathBuf>,
+
+        #[clap(long, help = "Replay archived events from this location (s3://bucket/prefix or file:///dir) instead of the WAL")]
+        archive: Option<String>,
+
+        #[clap(long, requires = "archive", help = "Only replay archives received on this date (YYYY-MM-DD)")]
+        date: Option<NaiveDate>,
+
+        #[clap(long, requires = "archive", help = "Only replay archives for this channel")]
+        channel: Option<String>,
+    },
+    /// Print the share of reports per version over time
+    Adoption {
+        #[clap(long, help = "First day, inclusive (YYYY-MM-DD)")]
+        from: NaiveDate,
+
+        #[clap(long, help = "Last day, inclusive (YYYY-MM-DD)")]
+        to: NaiveDate,
+
+        #[clap(long, default_value = "week", help = "Period length: day or week")]
+        granularity: Granularity,
+
+        #[clap(long, default_value_t = 5, help = "Versions reported on their own per platform and channel, the rest are summed as other")]
+        top: usize,
+
+        #[clap(long)]
+        metric_name: Option<String>,
+
+        #[clap(long)]
+        platform: Option<String>,
+
+        #[clap(long)]
+        channel: Option<String>,
+
+        #[clap(long, default_value = "csv", help = "Output format: json or csv")]
+        format: ReportFormat,
+    },
+    /// Write events received in a date range to a Parquet or CSV file
+    Export {
+        #[clap(long, help = "First day, inclusive (YYYY-MM-DD)")]
+        from: NaiveDate,
+
+        #[clap(long, help = "Last day, inclusive (YYYY-MM-DD)")]
+        to: NaiveDate,
+
+        #[clap(long)]
+        channel: Option<String>,
+
+        #[clap(long, help = "Metric name, or a prefix ending in * (e.g. Brave.Today.*)")]
+        metric: Option<String>,
+
+        #[clap(long, default_value = "parquet", help = "Output format: parquet or csv")]
+        format: ExportFormat,
+
+        #[clap(long, help = "Output file. Defaults to events-<from>-<to>.<format> in the current directory.")]
+        output: Option<PathBuf>,
+
+        #[clap(long, help = "Also upload the file to this location (s3://bucket/prefix or file:///dir)")]
+        upload: Option<String>,
+
+        #[clap(long, default_value_t = export::DEFAULT_CHUNK_SIZE, help = "Rows read from the database at a time")]
+        chunk_size: i64,
+    },
+    /// Manage the yearly partitions of telemetry_event_facts
+    Partitions {
+        #[clap(subcommand)]
+        action: PartitionsAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum PartitionsAction {
+    /// List existing partitions and their bounds
+    List,
+    /// Create the partition for a year, moving matching rows out of the default partition
+    Create {
+        #[clap(long)]
+        year: i32,
+    },
+}
+
+fn start_archiver() -> std::io::Result<Option<Addr<Archiver>>> {
+    let archiver = Archiver::from_env().map_err(std::io::Error::other)?;
+    Ok(archiver.map(Actor::start))
+}
+
+/// Waits for the archiver to upload everything it holds before exiting.
+async fn flush_archive(archive: Option<Addr<Archiver>>) {
+    if let Some(archive) = archive
+        && let Err(e) = archive.send(FlushArchive).await
+    {
+        log::error!("Could not flush the archive before exiting: {}", e);
+    }
+}
+
+async fn start_worker(
+    db_pool: Option<&Arc<DBPool>>,
+    sinks: &[SinkConfig],
+    archive: Option<Addr<Archiver>>,
+) -> std::io::Result<Addr<ActorWorker>> {
+    let sink = sink::build_sink(sinks, db_pool)?;
+    let wal = match wal::wal_dir_from_env() {
+        Some(dir) => {
+            match wal::replay(&dir, sink.as_ref()).await {
+                Ok(count) => log::info!("Replayed {} events from WAL", count),
+                Err(e) => log::error!("WAL replay failed, segments kept for next start: {}", e),
+            }
+            Some(Wal::open(dir, FsyncPolicy::from_env())?)
+        }
+        None => None,
+    };
+
+    Ok(ActorWorker {
+        sink,
         buffer: Default::default(),
-    }.start();
+        wal,
+        archive,
+    }.start())
+}
+
+/// Serves the imported catalog when there is a database with one, otherwise
+/// falls back to `EXTENSIONS_PATH`.
+async fn load_catalog(db_pool: Option<&DBPool>) -> Catalog {
+    if let Some(db_pool) = db_pool {
+        match import::load_entries(db_pool).await {
+            Ok(entries) if !entries.is_empty() => {
+                let catalog = Catalog::new(entries);
+                log::info!("Loaded {} components from the database", catalog.len());
+                return catalog;
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Could not load the component catalog from the database: {}", e),
+        }
+    }
+
+    let path = omaha::extensions_path_from_env();
+    match Catalog::load(&path) {
+        Ok(catalog) => {
+            log::info!("Loaded {} components from {}", catalog.len(), path.display());
+            catalog
+        }
+        Err(e) => {
+            log::warn!("Serving an empty component catalog, could not load {}: {}", path.display(), e);
+            Catalog::default()
+        }
+    }
+}
+
+async fn run_server(channel_name: DBConnectionType<'_>, bind: String, use_amqp: bool) -> std::io::Result<()> {
+    let sinks = SinkConfig::from_env();
+    // Without a Postgres sink there is no database at all. The AMQP front end
+    // doesn't write to it: connect lazily, so endpoints that read it fail
+    // until it's reachable but submissions are still accepted.
+    let db_pool = match (SinkConfig::uses_postgres(&sinks), use_amqp) {
+        (false, _) => None,
+        (true, false) => Some(Arc::new(DBPool::new(channel_name).await)),
+        (true, true) => Some(Arc::new(DBPool::new_lazy(channel_name))),
+    };
+    let package_store = web::Data::new(PackageStore::from_env().map_err(std::io::Error::other)?);
+    let catalog = web::Data::new(
+        load_catalog(db_pool.as_deref())
+            .await
+            .with_public_base_url(packages::public_base_url_from_env()),
+    );
+    if let Some(db_pool) = &db_pool {
+        match rollout::list_rollouts(db_pool).await {
+            Ok(rollouts) => catalog.set_rollouts(rollouts),
+            Err(e) => log::warn!("Serving without rollout rules, could not load them: {}", e),
+        }
+    }
+    if catalog.public_base_url().is_some() {
+        match package_store.hosted_packages().await {
+            Ok(hosted) => catalog.set_hosted(hosted),
+            Err(e) => log::warn!("Not advertising hosted packages, could not list the package store: {}", e),
+        }
+    }
+    let dedup = web::Data::new(DedupStore::from_env());
+    let geoip = GeoIp::from_env().map_err(std::io::Error::other)?.map(web::Data::new);
+    let registry = MetricRegistry::from_env().map_err(std::io::Error::other)?.map(web::Data::new);
+    // Parsed up front so a bad value stops startup instead of a request.
+    TemporalPolicy::from_env();
+    let archive = if use_amqp { None } else { start_archiver()? };
+    let queue = if use_amqp {
+        let publisher = AmqpPublisher::connect(&AmqpConfig::from_env())
+            .await
+            .map_err(std::io::Error::other)?;
+        EventQueue::Amqp(Box::new(publisher))
+    } else {
+        EventQueue::Worker(start_worker(db_pool.as_ref(), &sinks, archive.clone()).await?)
+    };
+    let queue = web::Data::new(queue);
+    let _retention = db_pool.clone().and_then(RetentionRefresher::from_env).map(Actor::start);
+    // Endpoints that read the database aren't available without one.
+    let db_pool = db_pool.map(web::Data::from);
 
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
-            .app_data(web::Data::new(worker_addr.clone()))
+            .wrap(AccessLog::from_env())
+            .app_data(queue.clone())
+            .app_data(catalog.clone())
+            .app_data(package_store.clone())
+            .app_data(dedup.clone())
+            .configure(|cfg| {
+                if let Some(db_pool) = &db_pool {
+                    cfg.app_data(db_pool.clone());
+                }
+                if let Some(geoip) = &geoip {
+                    cfg.app_data(geoip.clone());
+                }
+                if let Some(registry) = &registry {
+                    cfg.app_data(registry.clone());
+                }
+            })
             .route("/", web::get().to(|| async {
                 actix_web::HttpResponse::Ok()
                     .content_type("text/plain; charset=utf-8")
                     .body("Submission of privacy-preserving product analytics. See https://support.brave.com/hc/en-us/articles/9140465918093-What-is-P3A-in-Brave for details.")
             }))
+            .route("/metrics", web::get().to(metrics::metrics_handler))
             .service(service_scope()
             )
+            .service(update_scope())
+            .service(crx_scope())
 
         // เพิ่ม service, middleware อื่น ๆ ของคุณตรงนี้
     })
-        .bind(("0.0.0.0", 8011))?
+        .bind(bind)?
         .run()
-        .await
-}
\ No newline at end of file
+        .await?;
+    flush_archive(archive).await;
+    Ok(())
+}
+
+#[actix::main]
+async fn main() -> std::io::Result<()> {
+    dotenvy::dotenv().ok();
+    env_logger::init();
+    let cli_args = CliArgs::parse();
+    let channel_name = DBConnectionType::Normal { channel_name: &cli_args.main_channel_name.clone() };
+
+    match cli_args.command {
+        Command::Server { bind, amqp } => run_server(channel_name, bind, amqp).await,
+        Command::Worker => {
+            let sinks = SinkConfig::from_env();
+            let db_pool = if SinkConfig::uses_postgres(&sinks) {
+                Some(Arc::new(DBPool::new(channel_name).await))
+            } else {
+                None
+            };
+            let sink = sink::build_sink(&sinks, db_pool.as_ref())?;
+            let archive = start_archiver()?;
+            let result = amqp::run_consumer(&AmqpConfig::from_env(), sink, archive.clone()).await;
+            flush_archive(archive).await;
+            result.map_err(std::io::Error::other)
+        }
+        Command::Migrate => {
+            let db_pool = DBPool::new(channel_name).await;
+            db_pool.migrate().await.map_err(std::io::Error::other)?;
+            log::info!("Migrations applied");
+            Ok(())
+        }
+        Command::Replay { wal_dir, archive, date, channel } => {
+            let db_pool = Arc::new(DBPool::new(channel_name).await);
+            let count = match archive {
+                Some(url) => archive::replay(&url, date, channel.as_deref(), db_pool).await,
+                None => {
+                    let dir = wal_dir
+                        .or_else(wal::wal_dir_from_env)
+                        .expect("--wal-dir or WAL_DIR must be set");
+                    wal::replay(&dir, &PostgresSink::new(db_pool)).await
+                }
+            }
+            .map_err(std::io::Error::other)?;
+            println!("Replayed {} events", count);
+            Ok(())
+        }
+        Command::Adoption { from, to, granularity, top, metric_name, platform, channel, format } => {
+            let db_pool = DBPool::new(channel_name).await;
+            let query = AdoptionQuery { from, to, granularity, top: Some(top), metric_name, platform, channel, format };
+            let rows = adoption::adoption_report(&db_pool, &query)
+                .await
+                .map_err(std::io::Error::other)?;
+            match format {
+                ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+                ReportFormat::Csv => print!("{}", adoption::to_csv(&rows)),
+            }
+            Ok(())
+        }
+        Command::Export { from, to, channel, metric, format, output, upload, chunk_size } => {
+            let db_pool = DBPool::new(channel_name).await;
+            let output = output
+                .unwrap_or_else(|| PathBuf::from(format!("events-{}-{}.{}", from, to, format.extension())));
+            let options = ExportOptions { from, to, channel, metric, format, chunk_size };
+            let count = export::export(&db_pool, &options, &output)
+                .await
+                .map_err(std::io::Error::other)?;
+            println!("Exported {} events to {}", count, output.display());
+            if let Some(url) = upload {
+                let location = export::upload(&output, &url)
+                    .await
+                    .map_err(std::io::Error::other)?;
+                println!("Uploaded to {}", location);
+            }
+            Ok(())
+        }
+        Command::Partitions { action } => {
+            let db_pool = Arc::new(DBPool::new(channel_name).await);
+            match action {
+                PartitionsAction::List => {
+                    let list = partitions::list_partitions(db_pool)
+                        .await
+                        .map_err(std::io::Error::other)?;
+                    for partition in list {
+                        println!("{}\t{}", partition.name, partition.bound);
+                    }
+                }
+                PartitionsAction::Create { year } => {
+                    let created = partitions::create_yearly_partition(db_pool, year)
+                        .await
+                        .map_err(std::io::Error::other)?;
+                    let name = partitions::yearly_partition_name(year);
+                    if created {
+                        println!("Created partition {}", name);
+                    } else {
+                        println!("Partition {} already exists", name);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
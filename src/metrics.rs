@@ -0,0 +1,54 @@
+//! Process-wide ingestion counters, served in the Prometheus text format at
+//! `GET /metrics`.
+
+use actix_web::HttpResponse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+
+type Labels = Vec<(&'static str, String)>;
+
+fn counters() -> &'static Mutex<BTreeMap<(&'static str, Labels), u64>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<(&'static str, Labels), u64>>> = OnceLock::new();
+    COUNTERS.get_or_init(Default::default)
+}
+
+/// Adds one to the counter `name` with the given labels.
+pub fn increment(name: &'static str, labels: &[(&'static str, &str)]) {
+    let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    *counters().lock().unwrap().entry((name, labels)).or_default() += 1;
+}
+
+/// Current value of a counter, zero if it was never incremented.
+pub fn value(name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+    let labels: Labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    counters().lock().unwrap().get(&(name, labels)).copied().unwrap_or(0)
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+    let mut last_name = "";
+    for ((name, labels), value) in counters().lock().unwrap().iter() {
+        if *name != last_name {
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            last_name = name;
+        }
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(",");
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+    out
+}
+
+pub async fn metrics_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render())
+}
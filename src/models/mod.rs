@@ -12,7 +12,7 @@ use std::time::{Duration, Instant};
 use tokio::time::sleep;
 
 use crate::channel::get_data_channel_value_from_env;
-use crate::profiler::Profiler;
+use crate::dimensions::DimensionCache;
 const DATABASE_URL_ENV_KEY: &str = "DATABASE_URL";
 const TEST_DATABASE_URL_ENV_KEY: &str = "TEST_DATABASE_URL";
 const DATABASE_NAMES_ENV_KEY: &str = "p3a";
@@ -36,6 +36,8 @@ pub enum DBConnectionType<'a> {
 
 pub struct DBPool {
     pub(crate) inner_pool: Pool<Postgres>,
+    /// Dimension ids resolved by the event writer.
+    pub(crate) dimensions: DimensionCache,
 }
 
 fn get_channel_db_url(conn_type: &DBConnectionType<'_>) -> String {
@@ -72,7 +74,19 @@ impl DBPool {
             .await
             .expect("Could not connect to database");
 
-        Self { inner_pool: pool }
+        Self::from(pool)
+    }
+
+    /// Like [`DBPool::new`] but doesn't connect until the pool is first
+    /// used, so the server starts without a reachable database.
+    pub fn new_lazy(conn_type: DBConnectionType<'_>) -> Self {
+        let db_url = get_channel_db_url(&conn_type);
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(10))
+            .connect_lazy(&db_url)
+            .expect("Invalid database url");
+
+        Self::from(pool)
     }
 
     pub async fn get(&self) -> Result<PoolConnection<Postgres>, PgStoreError> {
@@ -87,6 +101,16 @@ impl DBPool {
         }
         Err(PgStoreError::PoolTimeout)
     }
+
+    pub async fn migrate(&self) -> Result<(), PgStoreError> {
+        sqlx::migrate!("./migrations")
+            .run(&self.inner_pool)
+            .await
+            .map_err(|e| {
+                log::error!("Migration failed: {}", e);
+                PgStoreError::Migration
+            })
+    }
 }
 
 pub struct DBStorageConnections {
@@ -112,8 +136,7 @@ impl DBStorageConnections {
     }
 
     pub fn get(&self) -> Arc<Mutex<PoolConnection<Postgres>>> {
-        use rand::{seq::SliceRandom, thread_rng};
-        self.conns.choose(&mut thread_rng()).unwrap().clone()
+        self.conns.choose(&mut rand::rng()).unwrap().clone()
     }
 
     pub fn commit(&self) -> Result<(), PgStoreError> {
@@ -127,13 +150,22 @@ impl DBStorageConnections {
 pub fn begin_db_transaction(
     conn: Arc<Mutex<PoolConnection<Postgres>>>,
 ) -> Result<(), PgStoreError> {
-    let guard = conn.lock().unwrap();
+    let _guard = conn.lock().unwrap();
     Ok(())
 }
 
 pub fn commit_db_transaction(
     conn: Arc<Mutex<PoolConnection<Postgres>>>,
 ) -> Result<(), PgStoreError> {
-    let guard = conn.lock().unwrap();
+    let _guard = conn.lock().unwrap();
     Ok(())
 }
+
+impl From<Pool<Postgres>> for DBPool {
+    fn from(inner_pool: Pool<Postgres>) -> Self {
+        Self {
+            inner_pool,
+            dimensions: DimensionCache::default(),
+        }
+    }
+}
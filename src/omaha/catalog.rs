@@ -0,0 +1,205 @@
+//! In-memory catalog of the releases of each component, built from an
+//! `extensions.json`-style document: a list of Omaha update-check responses,
+//! together with the staged rollout rules that choose between them.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::error::AppError;
+use crate::omaha::protocol::{Manifest, ResponseEnvelope};
+use crate::omaha::rollout::Rollout;
+
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    pub appid: String,
+    pub cohort: Option<String>,
+    pub cohortname: Option<String>,
+    pub codebases: Vec<String>,
+    pub manifest: Manifest,
+}
+
+/// Compares dotted numeric versions, treating missing components as zero so
+/// `1.2` equals `1.2.0`. Non-numeric components compare as zero.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Parses and validates a catalog document. Every app must carry an `ok`
+/// update check with at least one codebase and one well-formed package.
+pub fn parse_document(data: &[u8]) -> Result<Vec<CatalogEntry>, AppError> {
+    let responses: Vec<ResponseEnvelope> =
+        serde_json::from_slice(data).map_err(|e| AppError::SerdeError(e.to_string()))?;
+    let mut entries = Vec::new();
+    for response in responses {
+        for app in response.response.app {
+            let invalid = |reason: &str| AppError::BadRequest(format!("{}: {}", app.appid, reason));
+            if app.appid.is_empty() {
+                return Err(AppError::BadRequest("app without appid".to_string()));
+            }
+            let Some(updatecheck) = &app.updatecheck else {
+                return Err(invalid("missing updatecheck"));
+            };
+            if updatecheck.status != "ok" {
+                return Err(invalid("updatecheck status is not ok"));
+            }
+            let Some(manifest) = &updatecheck.manifest else {
+                return Err(invalid("missing manifest"));
+            };
+            let codebases: Vec<String> = updatecheck
+                .urls
+                .iter()
+                .flat_map(|urls| urls.url.iter().map(|url| url.codebase.clone()))
+                .collect();
+            if codebases.is_empty() {
+                return Err(invalid("no codebase urls"));
+            }
+            if manifest.version.is_empty() || manifest.packages.package.is_empty() {
+                return Err(invalid("manifest without version or packages"));
+            }
+            for package in &manifest.packages.package {
+                if package.name.is_empty() || package.size < 0 || !is_sha256_hex(&package.hash_sha256) {
+                    return Err(invalid("malformed package"));
+                }
+            }
+            entries.push(CatalogEntry {
+                appid: app.appid.clone(),
+                cohort: app.cohort.clone(),
+                cohortname: app.cohortname.clone(),
+                codebases,
+                manifest: manifest.clone(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Default)]
+pub struct Catalog {
+    /// Releases per appid, highest version last.
+    apps: RwLock<HashMap<String, Vec<CatalogEntry>>>,
+    rollouts: RwLock<HashMap<String, Vec<Rollout>>>,
+    public_base_url: Option<String>,
+    /// `(appid, version, name)` of packages present in the package store.
+    hosted: RwLock<HashSet<(String, String, String)>>,
+}
+
+impl Catalog {
+    pub fn new(entries: Vec<CatalogEntry>) -> Self {
+        let catalog = Self::default();
+        catalog.replace(entries);
+        catalog
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        Ok(Self::new(parse_document(&std::fs::read(path)?)?))
+    }
+
+    /// Also advertises codebases under `base_url`, where packages are self-hosted.
+    pub fn with_public_base_url(mut self, base_url: Option<String>) -> Self {
+        self.public_base_url = base_url;
+        self
+    }
+
+    pub fn public_base_url(&self) -> Option<&str> {
+        self.public_base_url.as_deref()
+    }
+
+    pub fn set_hosted(&self, packages: impl IntoIterator<Item = (String, String, String)>) {
+        *self.hosted.write().unwrap() = packages.into_iter().collect();
+    }
+
+    pub fn mark_hosted(&self, appid: &str, version: &str, name: &str) {
+        self.hosted
+            .write()
+            .unwrap()
+            .insert((appid.to_string(), version.to_string(), name.to_string()));
+    }
+
+    /// Whether every package of `entry` has been uploaded, so the hosted
+    /// codebase can be offered for it.
+    pub fn is_hosted(&self, entry: &CatalogEntry) -> bool {
+        let hosted = self.hosted.read().unwrap();
+        entry.manifest.packages.package.iter().all(|package| {
+            hosted.contains(&(entry.appid.clone(), entry.manifest.version.clone(), package.name.clone()))
+        })
+    }
+
+    /// Swaps in a new set of entries. Later duplicates of an appid and
+    /// version replace earlier ones.
+    pub fn replace(&self, entries: Vec<CatalogEntry>) {
+        let mut apps: HashMap<String, Vec<CatalogEntry>> = HashMap::new();
+        for entry in entries {
+            let versions = apps.entry(entry.appid.clone()).or_default();
+            versions.retain(|current| current.manifest.version != entry.manifest.version);
+            versions.push(entry);
+        }
+        for versions in apps.values_mut() {
+            versions.sort_by(|a, b| compare_versions(&a.manifest.version, &b.manifest.version));
+        }
+        *self.apps.write().unwrap() = apps;
+    }
+
+    pub fn set_rollouts(&self, rollouts: Vec<Rollout>) {
+        let mut by_appid: HashMap<String, Vec<Rollout>> = HashMap::new();
+        for rollout in rollouts {
+            by_appid.entry(rollout.appid.clone()).or_default().push(rollout);
+        }
+        *self.rollouts.write().unwrap() = by_appid;
+    }
+
+    pub fn rollouts(&self, appid: &str) -> Vec<Rollout> {
+        self.rollouts.read().unwrap().get(appid).cloned().unwrap_or_default()
+    }
+
+    /// Latest release of `appid`.
+    pub fn get(&self, appid: &str) -> Option<CatalogEntry> {
+        self.apps.read().unwrap().get(appid).and_then(|versions| versions.last()).cloned()
+    }
+
+    pub fn get_version(&self, appid: &str, version: &str) -> Option<CatalogEntry> {
+        self.apps
+            .read()
+            .unwrap()
+            .get(appid)?
+            .iter()
+            .find(|entry| compare_versions(&entry.manifest.version, version) == Ordering::Equal)
+            .cloned()
+    }
+
+    /// Highest release of `appid` older than `version`.
+    pub fn get_before(&self, appid: &str, version: &str) -> Option<CatalogEntry> {
+        self.apps
+            .read()
+            .unwrap()
+            .get(appid)?
+            .iter()
+            .rev()
+            .find(|entry| compare_versions(&entry.manifest.version, version) == Ordering::Less)
+            .cloned()
+    }
+
+    /// Number of distinct apps.
+    pub fn len(&self) -> usize {
+        self.apps.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
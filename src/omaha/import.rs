@@ -0,0 +1,245 @@
+//! Import of `extensions.json`-style documents into the extension catalog
+//! tables, and loading of the catalog back from them.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::omaha::protocol::{Manifest, Package, Packages};
+use crate::omaha::{extensions_path_from_env, parse_document, Catalog, CatalogEntry};
+use crate::storage::store_from_url;
+
+const IMPORT_SOURCE_DIR_ENV_KEY: &str = "IMPORT_SOURCE_DIR";
+
+/// `(id, appid, cohort, cohortname, version, codebases)`
+type VersionRow = (i64, String, Option<String>, Option<String>, String, Vec<String>);
+/// `(version_id, name, hash_sha256, size, fp, required, hash)`
+type PackageRow = (i64, String, String, i64, String, bool, Option<String>);
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct UpsertCounts {
+    pub inserted: u64,
+    pub updated: u64,
+    pub unchanged: u64,
+}
+
+impl UpsertCounts {
+    /// Records the outcome of an upsert: `Some(true)` for a new row,
+    /// `Some(false)` for a changed row, `None` when nothing was written.
+    fn record(&mut self, outcome: Option<bool>) {
+        match outcome {
+            Some(true) => self.inserted += 1,
+            Some(false) => self.updated += 1,
+            None => self.unchanged += 1,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub apps: UpsertCounts,
+    pub versions: UpsertCounts,
+    pub packages: UpsertCounts,
+}
+
+/// Upserts all entries in one transaction. Rows are only rewritten when a
+/// column actually changed, which is what separates updated from unchanged.
+pub async fn import_entries(pool: &DBPool, entries: &[CatalogEntry]) -> Result<ImportReport, sqlx::Error> {
+    let mut report = ImportReport::default();
+    let mut transaction = pool.inner_pool.begin().await?;
+
+    for entry in entries {
+        // `xmax = 0` only holds for freshly inserted rows.
+        let app_outcome: Option<bool> = sqlx::query_scalar(
+            r#"
+            INSERT INTO extension_apps (appid, cohort, cohortname)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (appid) DO UPDATE
+                SET cohort = EXCLUDED.cohort, cohortname = EXCLUDED.cohortname, updated_at = now()
+                WHERE (extension_apps.cohort, extension_apps.cohortname)
+                    IS DISTINCT FROM (EXCLUDED.cohort, EXCLUDED.cohortname)
+            RETURNING (xmax = 0)
+            "#,
+        )
+        .bind(&entry.appid)
+        .bind(&entry.cohort)
+        .bind(&entry.cohortname)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        report.apps.record(app_outcome);
+
+        let version_row: Option<(i64, bool)> = sqlx::query_as(
+            r#"
+            INSERT INTO extension_versions (appid, version, codebases)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (appid, version) DO UPDATE
+                SET codebases = EXCLUDED.codebases, updated_at = now()
+                WHERE extension_versions.codebases IS DISTINCT FROM EXCLUDED.codebases
+            RETURNING id, (xmax = 0)
+            "#,
+        )
+        .bind(&entry.appid)
+        .bind(&entry.manifest.version)
+        .bind(&entry.codebases)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        report.versions.record(version_row.map(|(_, inserted)| inserted));
+        let version_id = match version_row {
+            Some((id, _)) => id,
+            None => {
+                sqlx::query_scalar("SELECT id FROM extension_versions WHERE appid = $1 AND version = $2")
+                    .bind(&entry.appid)
+                    .bind(&entry.manifest.version)
+                    .fetch_one(&mut *transaction)
+                    .await?
+            }
+        };
+
+        for package in &entry.manifest.packages.package {
+            let package_outcome: Option<bool> = sqlx::query_scalar(
+                r#"
+                INSERT INTO extension_packages (version_id, name, hash_sha256, size, fp, required, hash)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (version_id, name) DO UPDATE
+                    SET hash_sha256 = EXCLUDED.hash_sha256, size = EXCLUDED.size, fp = EXCLUDED.fp,
+                        required = EXCLUDED.required, hash = EXCLUDED.hash, updated_at = now()
+                    WHERE (extension_packages.hash_sha256, extension_packages.size, extension_packages.fp,
+                           extension_packages.required, extension_packages.hash)
+                        IS DISTINCT FROM (EXCLUDED.hash_sha256, EXCLUDED.size, EXCLUDED.fp,
+                                          EXCLUDED.required, EXCLUDED.hash)
+                RETURNING (xmax = 0)
+                "#,
+            )
+            .bind(version_id)
+            .bind(&package.name)
+            .bind(&package.hash_sha256)
+            .bind(package.size)
+            .bind(&package.fp)
+            .bind(package.required)
+            .bind(&package.hash)
+            .fetch_optional(&mut *transaction)
+            .await?;
+            report.packages.record(package_outcome);
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(report)
+}
+
+/// Loads every stored version. Pass the result to [`Catalog::replace`] to
+/// serve the latest version of each app.
+pub async fn load_entries(pool: &DBPool) -> Result<Vec<CatalogEntry>, sqlx::Error> {
+    let versions: Vec<VersionRow> = sqlx::query_as(
+        r#"
+        SELECT v.id, a.appid, a.cohort, a.cohortname, v.version, v.codebases
+        FROM extension_versions v
+        JOIN extension_apps a ON a.appid = v.appid
+        "#,
+    )
+    .fetch_all(&pool.inner_pool)
+    .await?;
+
+    let packages: Vec<PackageRow> = sqlx::query_as(
+        "SELECT version_id, name, hash_sha256, size, fp, required, hash FROM extension_packages ORDER BY id",
+    )
+    .fetch_all(&pool.inner_pool)
+    .await?;
+    let mut packages_by_version: HashMap<i64, Vec<Package>> = HashMap::new();
+    for (version_id, name, hash_sha256, size, fp, required, hash) in packages {
+        packages_by_version.entry(version_id).or_default().push(Package {
+            hash_sha256,
+            size,
+            name,
+            fp,
+            required,
+            hash,
+        });
+    }
+
+    Ok(versions
+        .into_iter()
+        .map(|(id, appid, cohort, cohortname, version, codebases)| CatalogEntry {
+            appid,
+            cohort,
+            cohortname,
+            codebases,
+            manifest: Manifest {
+                version,
+                packages: Packages {
+                    package: packages_by_version.remove(&id).unwrap_or_default(),
+                },
+            },
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// `s3://bucket/key`, or a local path: `EXTENSIONS_PATH` itself or a
+    /// file under `IMPORT_SOURCE_DIR`. Defaults to `EXTENSIONS_PATH`.
+    pub source: Option<String>,
+}
+
+/// Resolves a local `source`, refusing anything but `EXTENSIONS_PATH` and
+/// files under `IMPORT_SOURCE_DIR`. Missing files are refused the same way,
+/// so the response doesn't reveal which paths exist.
+async fn allowed_local_source(source: &str) -> Result<PathBuf, AppError> {
+    let refused = || AppError::BadRequest(format!("source not allowed: {}", source));
+    let path = tokio::fs::canonicalize(source).await.map_err(|_| refused())?;
+    if tokio::fs::canonicalize(extensions_path_from_env())
+        .await
+        .is_ok_and(|extensions_path| extensions_path == path)
+    {
+        return Ok(path);
+    }
+    if let Some(dir) = env::var(IMPORT_SOURCE_DIR_ENV_KEY).ok().filter(|v| !v.is_empty())
+        && let Ok(dir) = tokio::fs::canonicalize(dir).await
+        && path.starts_with(&dir)
+    {
+        return Ok(path);
+    }
+    Err(refused())
+}
+
+async fn read_source(source: Option<&str>) -> Result<Vec<u8>, AppError> {
+    match source {
+        Some(url) if url.starts_with("s3://") => {
+            let (store, key) = store_from_url(url)?;
+            let data = store
+                .get(&key)
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?
+                .bytes()
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?;
+            Ok(data.to_vec())
+        }
+        Some(source) => Ok(tokio::fs::read(allowed_local_source(source).await?).await?),
+        None => Ok(tokio::fs::read(extensions_path_from_env()).await?),
+    }
+}
+
+/// Imports the document in the request body, or the one named by `source`,
+/// then reloads the served catalog from the database.
+pub async fn import_catalog(
+    pool: web::Data<DBPool>,
+    catalog: web::Data<Catalog>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let document = if body.is_empty() {
+        read_source(query.source.as_deref()).await?
+    } else {
+        body.to_vec()
+    };
+    let entries = parse_document(&document)?;
+    let db_error = |e: sqlx::Error| AppError::DatabaseError(e.to_string());
+    let report = import_entries(&pool, &entries).await.map_err(db_error)?;
+    catalog.replace(load_entries(&pool).await.map_err(db_error)?);
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,112 @@
+//! Converts the `ping` and `event` elements of update checks into telemetry
+//! events, so update success rates land in `telemetry_events` alongside the
+//! rest of the analytics.
+//!
+//! Each app yields `Omaha.<appid>.Ping` with value 1 for a ping, and
+//! `Omaha.<appid>.<EventType>` with a [`ResultBucket`] value for each event.
+//! Apps missing from the catalog are skipped and unknown event types are all
+//! named `Other`, so clients can't add metric names. The survey week is the
+//! week of the request. Update checks don't carry an install date, so the
+//! install week and year are left empty, which keeps them out of retention
+//! cohorts. The version is the browser's `prodversion`; the component's own
+//! version goes in the `component_version` extra field.
+
+use chrono::{DateTime, Datelike, Utc};
+use serde_json::{Map, Value};
+
+use crate::omaha::protocol::{AppRequest, EventRequest, Request};
+use crate::omaha::Catalog;
+use crate::payload::MyPayload;
+
+const CADENCE: &str = "typical";
+const DEFAULT_CHANNEL: &str = "release";
+const UNKNOWN: &str = "unknown";
+const UNKNOWN_COUNTRY: &str = "--";
+const COMPONENT_VERSION_KEY: &str = "component_version";
+
+/// Outcome of an Omaha event, stored as the metric value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ResultBucket {
+    Success = 0,
+    Error = 1,
+    Cancelled = 2,
+    Other = 3,
+}
+
+impl ResultBucket {
+    /// Maps Omaha `eventresult` codes: 1-3 are success variants, 0 is an
+    /// error and 4 a cancellation.
+    pub fn from_event(event: &EventRequest) -> Self {
+        match event.eventresult {
+            Some(1..=3) => ResultBucket::Success,
+            Some(0) => ResultBucket::Error,
+            Some(4) => ResultBucket::Cancelled,
+            _ => ResultBucket::Other,
+        }
+    }
+}
+
+fn event_name(eventtype: i32) -> &'static str {
+    match eventtype {
+        2 => "Install",
+        3 => "Update",
+        4 => "Uninstall",
+        14 => "Download",
+        41 => "UpdateCheck",
+        _ => "Other",
+    }
+}
+
+fn measurement(request: &Request, app: &AppRequest, metric_name: String, metric_value: i32, now: DateTime<Utc>) -> MyPayload {
+    let week = now.iso_week();
+    let platform = request
+        .os
+        .as_ref()
+        .and_then(|os| os.platform.as_deref())
+        .or(request.os_name.as_deref())
+        .unwrap_or(UNKNOWN);
+    let mut extra = Map::new();
+    if !app.version.is_empty() {
+        extra.insert(COMPONENT_VERSION_KEY.to_string(), Value::String(app.version.clone()));
+    }
+    MyPayload {
+        cadence: CADENCE.to_string(),
+        channel: request.prodchannel.clone().unwrap_or(DEFAULT_CHANNEL.to_string()),
+        country_code: UNKNOWN_COUNTRY.to_string(),
+        metric_name,
+        metric_value,
+        platform: platform.to_lowercase(),
+        version: request
+            .prodversion
+            .clone()
+            .filter(|version| !version.is_empty())
+            .unwrap_or(UNKNOWN.to_string()),
+        woi: None,
+        wos: Some(week.week() as i16),
+        yoi: None,
+        yos: week.year() as i16,
+        idempotency_key: None,
+        extra,
+    }
+}
+
+/// All measurements carried by one update-check request.
+pub fn measurements(catalog: &Catalog, request: &Request, now: DateTime<Utc>) -> Vec<MyPayload> {
+    let mut payloads = Vec::new();
+    for app in request.app.iter().filter(|app| catalog.get(&app.appid).is_some()) {
+        if app.ping.is_some() {
+            payloads.push(measurement(request, app, format!("Omaha.{}.Ping", app.appid), 1, now));
+        }
+        for event in &app.event {
+            payloads.push(measurement(
+                request,
+                app,
+                format!("Omaha.{}.{}", app.appid, event_name(event.eventtype)),
+                ResultBucket::from_event(event) as i32,
+                now,
+            ));
+        }
+    }
+    payloads
+}
@@ -0,0 +1,160 @@
+//! Omaha 3.1 update server for browser components.
+//!
+//! Clients POST an update-check request listing the components they have
+//! installed. Each app gets `noupdate` when it is current, or the manifest and
+//! codebase URLs of the newer version held in the [`Catalog`]. When packages
+//! are self-hosted (see [`packages`]) our own codebase is listed first, once
+//! the packages of that version have been uploaded.
+//! Staged rollouts (see [`rollout`]) can hold back or pin the offered version.
+//! Pings and events in the request are validated and queued as telemetry
+//! (see [`measurement`]); rejected ones are quarantined like submissions.
+
+mod catalog;
+pub mod import;
+pub mod measurement;
+pub mod packages;
+pub mod protocol;
+pub mod rollout;
+pub use catalog::*;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{NaiveDate, Timelike, Utc};
+use std::cmp::Ordering;
+use std::env;
+use std::path::PathBuf;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::quarantine;
+use crate::queue_job::EventQueue;
+use crate::registry::MetricRegistry;
+use crate::temporal::TemporalPolicy;
+use protocol::{
+    AppRequest, AppResponse, DayStart, PingResponse, Request, RequestEnvelope, Response,
+    ResponseEnvelope, UpdateCheckResponse, Url, Urls, PROTOCOL_VERSION,
+};
+
+const EXTENSIONS_PATH_ENV_KEY: &str = "EXTENSIONS_PATH";
+const EXTENSIONS_PATH_DEFAULT: &str = "extensions.json";
+const SERVER_NAME: &str = "prod";
+const STATUS_OK: &str = "ok";
+const STATUS_NOUPDATE: &str = "noupdate";
+const STATUS_UNKNOWN_APPLICATION: &str = "error-unknownApplication";
+
+pub fn extensions_path_from_env() -> PathBuf {
+    PathBuf::from(env::var(EXTENSIONS_PATH_ENV_KEY).unwrap_or(EXTENSIONS_PATH_DEFAULT.to_string()))
+}
+
+/// Days since 2007-01-01 and seconds since midnight, both UTC, as Omaha
+/// clients expect them.
+fn daystart() -> DayStart {
+    let now = Utc::now();
+    let epoch = NaiveDate::from_ymd_opt(2007, 1, 1).unwrap();
+    DayStart {
+        elapsed_seconds: now.num_seconds_from_midnight() as u64,
+        elapsed_days: (now.date_naive() - epoch).num_days() as u64,
+    }
+}
+
+fn respond_app(catalog: &Catalog, request: &Request, app: &AppRequest) -> AppResponse {
+    let Some(latest) = catalog.get(&app.appid) else {
+        return AppResponse {
+            appid: app.appid.clone(),
+            status: STATUS_UNKNOWN_APPLICATION.to_string(),
+            ..Default::default()
+        };
+    };
+
+    let platform = request
+        .os
+        .as_ref()
+        .and_then(|os| os.platform.as_deref())
+        .or(request.os_name.as_deref());
+    // sessionid changes between sessions, so only userid keeps buckets stable.
+    let client_id = request.userid.as_deref();
+    let target = rollout::resolve(catalog, latest, request.prodchannel.as_deref(), platform, client_id);
+
+    let updatecheck = app.updatecheck.as_ref().map(|check| {
+        let offered = target.entry.as_ref().filter(|entry| {
+            match compare_versions(&entry.manifest.version, &app.version) {
+                Ordering::Greater => true,
+                Ordering::Less => target.pinned && check.rollback_allowed == Some(true),
+                Ordering::Equal => false,
+            }
+        });
+        if let Some(entry) = offered
+            && check.updatedisabled != Some(true)
+        {
+            UpdateCheckResponse {
+                status: STATUS_OK.to_string(),
+                urls: Some(Urls {
+                    url: catalog
+                        .public_base_url()
+                        .filter(|_| catalog.is_hosted(entry))
+                        .map(|base_url| packages::hosted_codebase(base_url, &entry.appid, &entry.manifest.version))
+                        .into_iter()
+                        .chain(entry.codebases.iter().cloned())
+                        .map(|codebase| Url { codebase })
+                        .collect(),
+                }),
+                manifest: Some(entry.manifest.clone()),
+            }
+        } else {
+            UpdateCheckResponse {
+                status: STATUS_NOUPDATE.to_string(),
+                ..Default::default()
+            }
+        }
+    });
+
+    AppResponse {
+        appid: app.appid.clone(),
+        cohort: target.cohort,
+        status: STATUS_OK.to_string(),
+        cohortname: target.cohortname,
+        ping: app.ping.as_ref().map(|_| PingResponse { status: STATUS_OK.to_string() }),
+        updatecheck,
+    }
+}
+
+pub fn respond(catalog: &Catalog, request: &Request) -> Result<Response, AppError> {
+    if request.protocol != PROTOCOL_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "unsupported protocol version: {}",
+            request.protocol
+        )));
+    }
+    Ok(Response {
+        server: SERVER_NAME.to_string(),
+        protocol: PROTOCOL_VERSION.to_string(),
+        daystart: Some(daystart()),
+        app: request.app.iter().map(|app| respond_app(catalog, request, app)).collect(),
+    })
+}
+
+pub async fn update_check(
+    req: HttpRequest,
+    catalog: web::Data<Catalog>,
+    queue: Option<web::Data<EventQueue>>,
+    registry: Option<web::Data<MetricRegistry>>,
+    pool: Option<web::Data<DBPool>>,
+    item: web::Json<RequestEnvelope>,
+) -> Result<HttpResponse, AppError> {
+    let response = respond(&catalog, &item.request)?;
+    // Telemetry is best effort, the client still gets its update answer.
+    if let Some(queue) = queue {
+        let now = Utc::now();
+        for payload in measurement::measurements(&catalog, &item.request, now) {
+            let registry = registry.as_ref().map(|r| r.get_ref());
+            if let Err(rejection) = quarantine::validate(&payload, registry, TemporalPolicy::from_env(), now.date_naive()) {
+                let raw = serde_json::to_vec(&payload).unwrap_or_default();
+                quarantine::quarantine(pool.as_ref().map(|p| p.get_ref()), &req, &rejection, &raw).await;
+                continue;
+            }
+            if let Err(e) = queue.push(payload).await {
+                log::error!("Failed to queue update-check measurement: {}", e);
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(ResponseEnvelope { response }))
+}
@@ -0,0 +1,234 @@
+//! CRX package hosting.
+//!
+//! Packages are uploaded with `PUT /api/v1/crx/{appid}/{version}/{name}` and
+//! must match the `hash_sha256` and `size` of the imported manifest. They are
+//! served from `GET /crx/{appid}/{version}/{name}`, so the codebase handed to
+//! clients is `{PUBLIC_BASE_URL}/crx/{appid}/{version}/`.
+
+use actix_web::http::header::{self, ContentRange, ContentRangeSpec, Range};
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::{StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::ObjectStore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::omaha::Catalog;
+use crate::storage::store_from_url;
+
+const PACKAGE_STORE_URL_ENV_KEY: &str = "PACKAGE_STORE_URL";
+const PACKAGE_STORE_URL_DEFAULT: &str = "file://packages";
+const PUBLIC_BASE_URL_ENV_KEY: &str = "PUBLIC_BASE_URL";
+const CRX_CONTENT_TYPE: &str = "application/x-chrome-extension";
+/// Upload buffer reserved up front. The declared size comes from the
+/// catalog, so larger packages grow the buffer as data arrives instead.
+const UPLOAD_INITIAL_CAPACITY: usize = 1024 * 1024;
+
+/// Base URL clients reach this server at, if packages are self-hosted.
+pub fn public_base_url_from_env() -> Option<String> {
+    env::var(PUBLIC_BASE_URL_ENV_KEY).ok().filter(|v| !v.is_empty())
+}
+
+/// Codebase under which the packages of one app version are served.
+pub fn hosted_codebase(base_url: &str, appid: &str, version: &str) -> String {
+    format!("{}/crx/{}/{}/", base_url.trim_end_matches('/'), appid, version)
+}
+
+fn storage_err(e: object_store::Error) -> AppError {
+    match e {
+        object_store::Error::NotFound { path, .. } => AppError::NotFound(path),
+        e => AppError::StorageError(e.to_string()),
+    }
+}
+
+/// Rejects path segments that could escape the app/version/name layout.
+fn check_segment(segment: &str) -> Result<(), AppError> {
+    let valid = !segment.is_empty()
+        && !segment.starts_with('.')
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!("invalid path segment: {}", segment)))
+    }
+}
+
+pub struct PackageStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl PackageStore {
+    pub fn from_url(url: &str) -> Result<Self, AppError> {
+        let (store, prefix) = store_from_url(url)?;
+        Ok(Self { store, prefix })
+    }
+
+    /// Opens the store named by `PACKAGE_STORE_URL`, `file://packages` by default.
+    pub fn from_env() -> Result<Self, AppError> {
+        Self::from_url(&env::var(PACKAGE_STORE_URL_ENV_KEY).unwrap_or(PACKAGE_STORE_URL_DEFAULT.to_string()))
+    }
+
+    /// `(appid, version, name)` of every stored package.
+    pub async fn hosted_packages(&self) -> Result<Vec<(String, String, String)>, AppError> {
+        let skip = self.prefix.parts().count();
+        self.store
+            .list(Some(&self.prefix))
+            .try_filter_map(|meta| {
+                let parts: Vec<String> = meta.location.parts().skip(skip).map(|part| part.as_ref().to_string()).collect();
+                futures_util::future::ready(Ok(match <[String; 3]>::try_from(parts) {
+                    Ok([appid, version, name]) => Some((appid, version, name)),
+                    Err(_) => None,
+                }))
+            })
+            .try_collect()
+            .await
+            .map_err(storage_err)
+    }
+
+    fn location(&self, appid: &str, version: &str, name: &str) -> Result<Path, AppError> {
+        for segment in [appid, version, name] {
+            check_segment(segment)?;
+        }
+        Ok(self.prefix.child(appid).child(version).child(name))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedPackage {
+    pub name: String,
+    pub size: i64,
+    pub hash_sha256: String,
+}
+
+/// Stores a package after checking it against the manifest in the database.
+pub async fn upload_package(
+    pool: web::Data<DBPool>,
+    packages: web::Data<PackageStore>,
+    catalog: Option<web::Data<Catalog>>,
+    path: web::Path<(String, String, String)>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, AppError> {
+    let (appid, version, name) = path.into_inner();
+    let location = packages.location(&appid, &version, &name)?;
+
+    let expected: Option<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT p.hash_sha256, p.size
+        FROM extension_packages p
+        JOIN extension_versions v ON v.id = p.version_id
+        WHERE v.appid = $1 AND v.version = $2 AND p.name = $3
+        "#,
+    )
+    .bind(&appid)
+    .bind(&version)
+    .bind(&name)
+    .fetch_optional(&pool.inner_pool)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    let Some((expected_hash, expected_size)) = expected else {
+        return Err(AppError::NotFound(format!("no manifest lists {}/{}/{}", appid, version, name)));
+    };
+
+    let mut hasher = Sha256::new();
+    let mut data = Vec::with_capacity((expected_size as usize).min(UPLOAD_INITIAL_CAPACITY));
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(e.to_string()))?;
+        if (data.len() + chunk.len()) as i64 > expected_size {
+            return Err(AppError::BadRequest(format!("package is larger than {} bytes", expected_size)));
+        }
+        hasher.update(&chunk);
+        data.extend_from_slice(&chunk);
+    }
+    if data.len() as i64 != expected_size {
+        return Err(AppError::BadRequest(format!(
+            "package size {} does not match manifest size {}",
+            data.len(),
+            expected_size
+        )));
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    if !hash.eq_ignore_ascii_case(&expected_hash) {
+        return Err(AppError::BadRequest(format!(
+            "package hash {} does not match manifest hash {}",
+            hash, expected_hash
+        )));
+    }
+
+    packages
+        .store
+        .put(&location, data.into())
+        .await
+        .map_err(storage_err)?;
+    if let Some(catalog) = catalog {
+        catalog.mark_hosted(&appid, &version, &name);
+    }
+    Ok(HttpResponse::Created().json(UploadedPackage {
+        name,
+        size: expected_size,
+        hash_sha256: hash,
+    }))
+}
+
+/// Serves a package, honouring a single byte range so interrupted
+/// downloads can resume.
+pub async fn download_package(
+    req: HttpRequest,
+    packages: web::Data<PackageStore>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (appid, version, name) = path.into_inner();
+    let location = packages.location(&appid, &version, &name)?;
+    let meta = packages.store.head(&location).await.map_err(storage_err)?;
+    let length = meta.size;
+
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Range>().ok());
+    if let Some(Range::Bytes(specs)) = range
+        && let [spec] = specs.as_slice()
+    {
+        let Some((start, end)) = spec.to_satisfiable_range(length) else {
+            return Ok(HttpResponse::RangeNotSatisfiable()
+                .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: None,
+                    instance_length: Some(length),
+                }))
+                .finish());
+        };
+        let data = packages
+            .store
+            .get_range(&location, start..end + 1)
+            .await
+            .map_err(storage_err)?;
+        return Ok(HttpResponse::PartialContent()
+            .content_type(CRX_CONTENT_TYPE)
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start, end)),
+                instance_length: Some(length),
+            }))
+            .body(data));
+    }
+
+    let stream = packages
+        .store
+        .get(&location)
+        .await
+        .map_err(storage_err)?
+        .into_stream()
+        .map_err(|e| AppError::StorageError(e.to_string()));
+    Ok(HttpResponse::Ok()
+        .content_type(CRX_CONTENT_TYPE)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CONTENT_LENGTH, length))
+        .streaming(stream))
+}
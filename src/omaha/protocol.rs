@@ -0,0 +1,170 @@
+//! Omaha protocol 3.1 JSON messages, as sent by the Chromium component
+//! updater and stored in `extensions.json`.
+
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: &str = "3.1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestEnvelope {
+    pub request: Request,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Request {
+    pub protocol: String,
+    #[serde(rename = "@os", default)]
+    pub os_name: Option<String>,
+    #[serde(default)]
+    pub os: Option<RequestOs>,
+    #[serde(default)]
+    pub arch: Option<String>,
+    #[serde(default)]
+    pub prodchannel: Option<String>,
+    #[serde(default)]
+    pub prodversion: Option<String>,
+    #[serde(default)]
+    pub requestid: Option<String>,
+    #[serde(default)]
+    pub sessionid: Option<String>,
+    /// Stable client identifier used for rollout bucketing, if sent.
+    #[serde(default)]
+    pub userid: Option<String>,
+    #[serde(default)]
+    pub app: Vec<AppRequest>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestOs {
+    #[serde(default)]
+    pub platform: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppRequest {
+    pub appid: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub cohort: Option<String>,
+    #[serde(default)]
+    pub updatecheck: Option<UpdateCheckRequest>,
+    #[serde(default)]
+    pub ping: Option<PingRequest>,
+    #[serde(default)]
+    pub event: Vec<EventRequest>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateCheckRequest {
+    #[serde(default)]
+    pub updatedisabled: Option<bool>,
+    /// The client accepts an older version than the one it runs.
+    #[serde(default)]
+    pub rollback_allowed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PingRequest {
+    #[serde(default)]
+    pub r: Option<i32>,
+    #[serde(default)]
+    pub rd: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventRequest {
+    pub eventtype: i32,
+    #[serde(default)]
+    pub eventresult: Option<i32>,
+    #[serde(default)]
+    pub errorcode: Option<i32>,
+    #[serde(default)]
+    pub previousversion: Option<String>,
+    #[serde(default)]
+    pub nextversion: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseEnvelope {
+    pub response: Response,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub server: String,
+    pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daystart: Option<DayStart>,
+    pub app: Vec<AppResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayStart {
+    pub elapsed_seconds: u64,
+    pub elapsed_days: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppResponse {
+    pub appid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cohortname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping: Option<PingResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updatecheck: Option<UpdateCheckResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateCheckResponse {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Urls>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<Manifest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Urls {
+    pub url: Vec<Url>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Url {
+    pub codebase: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub packages: Packages,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Packages {
+    pub package: Vec<Package>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub hash_sha256: String,
+    pub size: i64,
+    pub name: String,
+    pub fp: String,
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
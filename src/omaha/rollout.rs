@@ -0,0 +1,220 @@
+//! Staged rollouts of component versions.
+//!
+//! A rule targets one app on a release channel and platform, either of which
+//! may be `*`, and the most specific matching rule decides what is offered.
+//! Pinned rules offer their version to every client, which is also how a
+//! release is rolled back: clients already on a newer version are offered
+//! the pinned one as a downgrade when their update check sets
+//! `rollback_allowed`, and are left alone otherwise. Other rules offer
+//! their version to `percentage` percent of clients, bucketed by a hash of
+//! the appid and the client's `userid`, and everyone else is offered the
+//! release before it.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::omaha::{Catalog, CatalogEntry};
+
+/// Matches any channel or platform.
+pub const ANY: &str = "*";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Rollout {
+    pub appid: String,
+    pub channel: String,
+    pub platform: String,
+    pub version: String,
+    pub percentage: i16,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub cohort: Option<String>,
+    #[serde(default)]
+    pub cohortname: Option<String>,
+}
+
+impl Rollout {
+    /// How closely the rule matches, or `None` if it does not apply. An exact
+    /// channel outweighs an exact platform.
+    fn specificity(&self, channel: Option<&str>, platform: Option<&str>) -> Option<u8> {
+        let score = |rule: &str, value: Option<&str>, weight: u8| {
+            if rule == ANY {
+                Some(0)
+            } else if value.is_some_and(|value| value.eq_ignore_ascii_case(rule)) {
+                Some(weight)
+            } else {
+                None
+            }
+        };
+        Some(score(&self.channel, channel, 2)? + score(&self.platform, platform, 1)?)
+    }
+}
+
+/// Stable bucket in `0..100` for a client and app.
+pub fn bucket(appid: &str, client_id: &str) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(appid.as_bytes())
+        .chain_update(b"/")
+        .chain_update(client_id.as_bytes())
+        .finalize();
+    let value = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    (value % 100) as u8
+}
+
+/// What a client is offered for one app.
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    /// Release to offer, `None` when no release is eligible.
+    pub entry: Option<CatalogEntry>,
+    pub cohort: Option<String>,
+    pub cohortname: Option<String>,
+    /// Set by a pinned rule: the entry may be older than the client's
+    /// version and is then offered as a rollback.
+    pub pinned: bool,
+}
+
+/// Applies the rollout rules of a known app. Clients without an identifier
+/// are only included in pinned or 100% rollouts.
+pub fn resolve(
+    catalog: &Catalog,
+    latest: CatalogEntry,
+    channel: Option<&str>,
+    platform: Option<&str>,
+    client_id: Option<&str>,
+) -> Target {
+    let rule = catalog
+        .rollouts(&latest.appid)
+        .into_iter()
+        .filter_map(|rule| rule.specificity(channel, platform).map(|score| (score, rule)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, rule)| rule);
+    let Some(rule) = rule else {
+        return Target {
+            cohort: latest.cohort.clone(),
+            cohortname: latest.cohortname.clone(),
+            entry: Some(latest),
+            pinned: false,
+        };
+    };
+
+    let included = rule.pinned
+        || rule.percentage >= 100
+        || client_id.is_some_and(|id| i16::from(bucket(&rule.appid, id)) < rule.percentage);
+    if included {
+        Target {
+            entry: catalog.get_version(&rule.appid, &rule.version),
+            cohort: rule.cohort.or(latest.cohort),
+            cohortname: rule.cohortname.or(latest.cohortname),
+            pinned: rule.pinned,
+        }
+    } else {
+        let entry = catalog.get_before(&rule.appid, &rule.version);
+        Target {
+            cohort: entry.as_ref().map_or(latest.cohort, |entry| entry.cohort.clone()),
+            cohortname: entry.as_ref().map_or(latest.cohortname, |entry| entry.cohortname.clone()),
+            entry,
+            pinned: false,
+        }
+    }
+}
+
+pub async fn list_rollouts(pool: &DBPool) -> Result<Vec<Rollout>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT appid, channel, platform, version, percentage, pinned, cohort, cohortname
+        FROM extension_rollouts
+        ORDER BY appid, channel, platform
+        "#,
+    )
+    .fetch_all(&pool.inner_pool)
+    .await
+}
+
+pub async fn upsert_rollout(pool: &DBPool, rollout: &Rollout) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO extension_rollouts (appid, channel, platform, version, percentage, pinned, cohort, cohortname)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (appid, channel, platform) DO UPDATE
+            SET version = EXCLUDED.version, percentage = EXCLUDED.percentage, pinned = EXCLUDED.pinned,
+                cohort = EXCLUDED.cohort, cohortname = EXCLUDED.cohortname, updated_at = now()
+        "#,
+    )
+    .bind(&rollout.appid)
+    .bind(&rollout.channel)
+    .bind(&rollout.platform)
+    .bind(&rollout.version)
+    .bind(rollout.percentage)
+    .bind(rollout.pinned)
+    .bind(&rollout.cohort)
+    .bind(&rollout.cohortname)
+    .execute(&pool.inner_pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_rollout(pool: &DBPool, appid: &str, channel: &str, platform: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM extension_rollouts WHERE appid = $1 AND channel = $2 AND platform = $3")
+        .bind(appid)
+        .bind(channel)
+        .bind(platform)
+        .execute(&pool.inner_pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+fn db_error(e: sqlx::Error) -> AppError {
+    AppError::DatabaseError(e.to_string())
+}
+
+async fn reload(pool: &DBPool, catalog: &Catalog) -> Result<(), AppError> {
+    catalog.set_rollouts(list_rollouts(pool).await.map_err(db_error)?);
+    Ok(())
+}
+
+pub async fn get_rollouts(pool: web::Data<DBPool>) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(list_rollouts(&pool).await.map_err(db_error)?))
+}
+
+/// Creates or replaces the rule for an app, channel and platform.
+pub async fn put_rollout(
+    pool: web::Data<DBPool>,
+    catalog: web::Data<Catalog>,
+    item: web::Json<Rollout>,
+) -> Result<HttpResponse, AppError> {
+    let rollout = item.into_inner();
+    if !(0..=100).contains(&rollout.percentage) {
+        return Err(AppError::BadRequest("percentage must be between 0 and 100".to_string()));
+    }
+    let known: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM extension_versions WHERE appid = $1 AND version = $2)",
+    )
+    .bind(&rollout.appid)
+    .bind(&rollout.version)
+    .fetch_one(&pool.inner_pool)
+    .await
+    .map_err(db_error)?;
+    if !known {
+        return Err(AppError::NotFound(format!("{} has no version {}", rollout.appid, rollout.version)));
+    }
+
+    upsert_rollout(&pool, &rollout).await.map_err(db_error)?;
+    reload(&pool, &catalog).await?;
+    Ok(HttpResponse::Ok().json(rollout))
+}
+
+pub async fn remove_rollout(
+    pool: web::Data<DBPool>,
+    catalog: web::Data<Catalog>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (appid, channel, platform) = path.into_inner();
+    if !delete_rollout(&pool, &appid, &channel, &platform).await.map_err(db_error)? {
+        return Err(AppError::NotFound(format!("no rollout for {}/{}/{}", appid, channel, platform)));
+    }
+    reload(&pool, &catalog).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,81 @@
+//! Maintenance of the yearly range partitions of `telemetry_event_facts`.
+//!
+//! Rows whose `received_at` has no matching partition land in
+//! `telemetry_event_facts_default`. Creating a partition moves those rows out of the
+//! default partition before attaching the new one, since Postgres refuses to
+//! attach a range that the default partition already holds rows for.
+
+use std::sync::Arc;
+
+use crate::models::DBPool;
+
+const PARENT_TABLE: &str = "telemetry_event_facts";
+const DEFAULT_PARTITION: &str = "telemetry_event_facts_default";
+
+pub struct PartitionInfo {
+    pub name: String,
+    pub bound: String,
+}
+
+pub fn yearly_partition_name(year: i32) -> String {
+    format!("{}_y{}", PARENT_TABLE, year)
+}
+
+pub async fn list_partitions(pool: Arc<DBPool>) -> Result<Vec<PartitionInfo>, sqlx::Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT c.relname::text, pg_get_expr(c.relpartbound, c.oid)
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = $1::regclass
+        ORDER BY c.relname
+        "#,
+    )
+    .bind(PARENT_TABLE)
+    .fetch_all(&pool.inner_pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(name, bound)| PartitionInfo { name, bound })
+        .collect())
+}
+
+/// Creates the partition for `year` unless it already exists. Returns whether
+/// a partition was created.
+pub async fn create_yearly_partition(pool: Arc<DBPool>, year: i32) -> Result<bool, sqlx::Error> {
+    let name = yearly_partition_name(year);
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(&name)
+        .fetch_one(&pool.inner_pool)
+        .await?;
+    if exists {
+        return Ok(false);
+    }
+
+    // Identifiers can't be bound, but the name and bounds are derived from an
+    // integer year so they are safe to format in.
+    let from = format!("{}-01-01", year);
+    let to = format!("{}-01-01", year + 1);
+    let mut transaction = pool.inner_pool.begin().await?;
+    let statements = [
+        format!(
+            "CREATE TABLE {name} (LIKE {PARENT_TABLE} INCLUDING DEFAULTS INCLUDING CONSTRAINTS)"
+        ),
+        format!(
+            "INSERT INTO {name} SELECT * FROM {DEFAULT_PARTITION} \
+             WHERE received_at >= '{from}' AND received_at < '{to}'"
+        ),
+        format!(
+            "DELETE FROM {DEFAULT_PARTITION} WHERE received_at >= '{from}' AND received_at < '{to}'"
+        ),
+        // Attaching builds the indexes defined on the parent.
+        format!(
+            "ALTER TABLE {PARENT_TABLE} ATTACH PARTITION {name} FOR VALUES FROM ('{from}') TO ('{to}')"
+        ),
+    ];
+    for statement in statements {
+        sqlx::query(&statement).execute(&mut *transaction).await?;
+    }
+    transaction.commit().await?;
+    Ok(true)
+}
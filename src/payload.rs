@@ -1,4 +1,11 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::env;
+use std::sync::OnceLock;
+
+const PAYLOAD_EXTRA_KEYS_ENV_KEY: &str = "PAYLOAD_EXTRA_KEYS";
+const PAYLOAD_EXTRA_KEYS_DEFAULT: &str = "";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MyPayload {
@@ -9,8 +16,53 @@ pub struct MyPayload {
     pub metric_value: i32,
     pub platform: String,
     pub version: String,
-    pub woi: i16,
+    pub woi: Option<i16>,
     pub wos: Option<i16>,
-    pub yoi: i16,
+    pub yoi: Option<i16>,
     pub yos: i16,
+    /// Client-supplied key identifying retries of the same submission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Fields outside the schema above, stored in the `extra` JSONB column.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Allow-list of extra fields kept at ingestion, read from the
+/// comma-separated `PAYLOAD_EXTRA_KEYS`. `*` keeps every field; by default
+/// all of them are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtraKeys {
+    All,
+    Only(HashSet<String>),
+}
+
+impl ExtraKeys {
+    pub fn parse(list: &str) -> Self {
+        let keys: HashSet<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        if keys.contains("*") {
+            ExtraKeys::All
+        } else {
+            ExtraKeys::Only(keys)
+        }
+    }
+
+    pub fn from_env() -> &'static Self {
+        static KEYS: OnceLock<ExtraKeys> = OnceLock::new();
+        KEYS.get_or_init(|| {
+            ExtraKeys::parse(&env::var(PAYLOAD_EXTRA_KEYS_ENV_KEY).unwrap_or(PAYLOAD_EXTRA_KEYS_DEFAULT.to_string()))
+        })
+    }
+
+    /// Drops the extra fields that are not allow-listed.
+    pub fn retain(&self, payload: &mut MyPayload) {
+        if let ExtraKeys::Only(keys) = self {
+            payload.extra.retain(|key, _| keys.contains(key));
+        }
+    }
 }
@@ -100,19 +100,16 @@ impl Profiler {
   pub async fn record_range(&self, key: ProfilerStat, value: u32, unit: &'static str) {
     if !self.stats.read().await.contains_key(&key) {
       let mut stats = self.stats.write().await;
-      // Check key again to handle potential race condition
-      if !stats.contains_key(&key) {
-        stats.insert(
-          key,
-          Mutex::new(StatInfo::Range {
-            unit,
-            min: u32::MAX,
-            max: 0,
-            sum: 0,
-            entries: BinaryHeap::with_capacity(2000),
-          }),
-        );
-      }
+      // Use entry to handle potential race condition
+      stats.entry(key).or_insert_with(|| {
+        Mutex::new(StatInfo::Range {
+          unit,
+          min: u32::MAX,
+          max: 0,
+          sum: 0,
+          entries: BinaryHeap::with_capacity(2000),
+        })
+      });
     }
     let stats = self.stats.read().await;
     let mut stat_info = stats.get(&key).unwrap().lock().await;
@@ -0,0 +1,264 @@
+//! Quarantine for rejected submissions.
+//!
+//! Bodies that fail to parse or validate are kept in `telemetry_quarantine`
+//! with the reason and the raw JSON, instead of being dropped. Admins browse
+//! them with `GET /api/v1/quarantine` and push them through validation again
+//! with `POST /api/v1/quarantine/resubmit` once the rules have changed.
+//!
+//! Writes are rate limited per client with `RATE_LIMIT_QUARANTINE`, see
+//! [`crate::ratelimit`], defaulting to `ip:1:20`. Bodies over the limit are
+//! still rejected but not stored. Country mismatches can't be resubmitted as
+//! the client address needed to check them again isn't kept.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::error::AppError;
+use crate::metrics;
+use crate::models::DBPool;
+use crate::payload::{ExtraKeys, MyPayload};
+use crate::queue_job::EventQueue;
+use crate::ratelimit::RateLimiter;
+use crate::registry::{MetricRegistry, MetricViolation, RegistryMode};
+use crate::temporal::{TemporalPolicy, TemporalViolation};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+const QUARANTINE_RATE_LIMIT_DEFAULT: &str = "ip:1:20";
+pub const COUNTRY_MISMATCH: &str = "country_mismatch";
+
+/// Why a submission was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub reason: String,
+    pub detail: String,
+}
+
+impl Rejection {
+    pub fn new(reason: &str, detail: impl Into<String>) -> Self {
+        Self {
+            reason: reason.to_string(),
+            detail: detail.into(),
+        }
+    }
+}
+
+impl From<MetricViolation> for Rejection {
+    fn from(violation: MetricViolation) -> Self {
+        Rejection::new(violation.kind(), violation.to_string())
+    }
+}
+
+impl From<TemporalViolation> for Rejection {
+    fn from(violation: TemporalViolation) -> Self {
+        Rejection::new(violation.kind(), violation.to_string())
+    }
+}
+
+/// Runs every rule a parsed submission must pass. Registry violations other
+/// than unknown metrics only reject in [`RegistryMode::Reject`].
+pub fn validate(
+    payload: &MyPayload,
+    registry: Option<&MetricRegistry>,
+    temporal: &TemporalPolicy,
+    today: NaiveDate,
+) -> Result<(), Rejection> {
+    if let Err(violation) = temporal.check(payload, today) {
+        metrics::increment("temporal_violations_total", &[("reason", violation.kind())]);
+        return Err(violation.into());
+    }
+    if let Some(registry) = registry
+        && let Err(violation) = registry.check(payload, today)
+    {
+        metrics::increment("registry_violations_total", &[("reason", violation.kind())]);
+        match registry.mode {
+            RegistryMode::Reject => return Err(violation.into()),
+            // Unknown names would add a dimension value for anything a client sends.
+            RegistryMode::Warn if violation == MetricViolation::UnknownMetric => return Err(violation.into()),
+            RegistryMode::Warn => log::warn!("Accepting {}: {}", payload.metric_name, violation),
+        }
+    }
+    Ok(())
+}
+
+pub async fn insert_quarantined(pool: &DBPool, rejection: &Rejection, raw: &[u8]) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("INSERT INTO telemetry_quarantine (reason, detail, raw) VALUES ($1, $2, $3) RETURNING id")
+        .bind(&rejection.reason)
+        .bind(&rejection.detail)
+        .bind(String::from_utf8_lossy(raw).as_ref())
+        .fetch_one(&pool.inner_pool)
+        .await
+}
+
+fn limiter() -> &'static RateLimiter {
+    static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+    LIMITER.get_or_init(|| RateLimiter::from_env_or("quarantine", Some(QUARANTINE_RATE_LIMIT_DEFAULT)))
+}
+
+/// Stores a rejected body when a database is available and the client is
+/// within its quarantine limit. Failures are logged, the client gets its
+/// rejection either way.
+pub async fn quarantine(pool: Option<&DBPool>, req: &HttpRequest, rejection: &Rejection, raw: &[u8]) {
+    metrics::increment("quarantined_events_total", &[("reason", &rejection.reason)]);
+    let Some(pool) = pool else {
+        return;
+    };
+    if limiter().check(req).is_err() {
+        return;
+    }
+    if let Err(e) = insert_quarantined(pool, rejection, raw).await {
+        log::error!("Failed to quarantine event ({}): {}", rejection.reason, e);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct QuarantinedEvent {
+    pub id: i64,
+    pub reason: String,
+    pub detail: String,
+    pub raw: String,
+    pub received_at: DateTime<Utc>,
+    pub resubmitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct QuarantineQuery {
+    pub reason: Option<String>,
+    /// Only rows with a smaller id, for paging backwards.
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+    /// Also list rows that were already resubmitted.
+    #[serde(default)]
+    pub include_resubmitted: bool,
+}
+
+/// Newest first.
+pub async fn list_quarantined(pool: &DBPool, query: &QuarantineQuery) -> Result<Vec<QuarantinedEvent>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT id, reason, detail, raw, received_at, resubmitted_at
+        FROM telemetry_quarantine
+        WHERE ($1::text IS NULL OR reason = $1)
+          AND ($2::bigint IS NULL OR id < $2)
+          AND ($3 OR resubmitted_at IS NULL)
+        ORDER BY id DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(&query.reason)
+    .bind(query.before_id)
+    .bind(query.include_resubmitted)
+    .bind(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE))
+    .fetch_all(&pool.inner_pool)
+    .await
+}
+
+fn db_error(e: sqlx::Error) -> AppError {
+    AppError::DatabaseError(e.to_string())
+}
+
+pub async fn browse_quarantine(
+    pool: web::Data<DBPool>,
+    query: web::Query<QuarantineQuery>,
+) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(list_quarantined(&pool, &query).await.map_err(db_error)?))
+}
+
+/// Selects pending rows by id, by reason, or both.
+#[derive(Debug, Deserialize)]
+pub struct ResubmitRequest {
+    pub ids: Option<Vec<i64>>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ResubmitReport {
+    pub resubmitted: u64,
+    pub rejected: u64,
+}
+
+/// Validates pending rows against the current rules. Passing events are
+/// marked resubmitted and queued, the others keep their row with an updated
+/// reason. Rows are claimed before they are queued, so retrying a request
+/// that failed part way doesn't queue them twice.
+pub async fn resubmit_quarantine(
+    pool: web::Data<DBPool>,
+    queue: web::Data<EventQueue>,
+    registry: Option<web::Data<MetricRegistry>>,
+    item: web::Json<ResubmitRequest>,
+) -> Result<HttpResponse, AppError> {
+    if item.ids.is_none() && item.reason.is_none() {
+        return Err(AppError::BadRequest("ids or reason is required".to_string()));
+    }
+    if item.reason.as_deref() == Some(COUNTRY_MISMATCH) {
+        return Err(AppError::BadRequest(format!("{} can't be resubmitted", COUNTRY_MISMATCH)));
+    }
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        r#"
+        SELECT id, raw
+        FROM telemetry_quarantine
+        WHERE resubmitted_at IS NULL
+          AND reason <> $3
+          AND ($1::bigint[] IS NULL OR id = ANY($1))
+          AND ($2::text IS NULL OR reason = $2)
+        ORDER BY id
+        LIMIT $4
+        "#,
+    )
+    .bind(&item.ids)
+    .bind(&item.reason)
+    .bind(COUNTRY_MISMATCH)
+    .bind(MAX_PAGE_SIZE)
+    .fetch_all(&pool.inner_pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut report = ResubmitReport::default();
+    for (id, raw) in rows {
+        // Resubmitted events are stored as received now, so they are judged
+        // like a submission made today.
+        let checked = serde_json::from_str::<MyPayload>(&raw)
+            .map_err(|e| Rejection::new("invalid_json", e.to_string()))
+            .and_then(|payload| {
+                let registry = registry.as_ref().map(|r| r.get_ref());
+                validate(&payload, registry, TemporalPolicy::from_env(), Utc::now().date_naive()).map(|_| payload)
+            });
+        match checked {
+            Ok(mut payload) => {
+                ExtraKeys::from_env().retain(&mut payload);
+                let claimed = sqlx::query(
+                    "UPDATE telemetry_quarantine SET resubmitted_at = now() WHERE id = $1 AND resubmitted_at IS NULL",
+                )
+                .bind(id)
+                .execute(&pool.inner_pool)
+                .await
+                .map_err(db_error)?;
+                if claimed.rows_affected() == 0 {
+                    continue;
+                }
+                if let Err(e) = queue.push(payload).await {
+                    sqlx::query("UPDATE telemetry_quarantine SET resubmitted_at = NULL WHERE id = $1")
+                        .bind(id)
+                        .execute(&pool.inner_pool)
+                        .await
+                        .map_err(db_error)?;
+                    return Err(e);
+                }
+                report.resubmitted += 1;
+            }
+            Err(rejection) => {
+                sqlx::query("UPDATE telemetry_quarantine SET reason = $2, detail = $3 WHERE id = $1")
+                    .bind(id)
+                    .bind(&rejection.reason)
+                    .bind(&rejection.detail)
+                    .execute(&pool.inner_pool)
+                    .await
+                    .map_err(db_error)?;
+                report.rejected += 1;
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,169 @@
+//! Read access to stored events, served at `GET /api/v1/events`.
+//!
+//! Filters match the dimension values exactly, except for versions, which
+//! are compared by their parsed components: `version_min=1.70` matches
+//! `1.70.0` and every later version, and `latest_lines=3` keeps the three
+//! newest release lines among the rows matching the other filters. Brave
+//! keeps the major component at 1, so a release line is major.minor (1.80,
+//! 1.79, ...). Versions that could not be parsed never match a version filter.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::error::AppError;
+use crate::models::DBPool;
+use crate::version::Version;
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventQuery {
+    pub metric_name: Option<String>,
+    pub channel: Option<String>,
+    pub platform: Option<String>,
+    pub country_code: Option<String>,
+    /// Lowest version, inclusive.
+    pub version_min: Option<String>,
+    /// Highest version, inclusive.
+    pub version_max: Option<String>,
+    pub latest_lines: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Only rows with a smaller id, for paging backwards.
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct StoredEvent {
+    pub id: i64,
+    pub cadence: String,
+    pub channel: String,
+    pub country_code: String,
+    pub metric_name: String,
+    pub metric_value: i32,
+    pub platform: String,
+    pub version: String,
+    pub woi: Option<i16>,
+    pub wos: Option<i16>,
+    pub yoi: Option<i16>,
+    pub yos: i16,
+    pub received_at: DateTime<Utc>,
+}
+
+fn parse_version(value: &Option<String>) -> Result<Option<Version>, AppError> {
+    value
+        .as_deref()
+        .map(|v| v.parse::<Version>().map_err(AppError::BadRequest))
+        .transpose()
+}
+
+fn push_version_bound(builder: &mut QueryBuilder<'_, Postgres>, operator: &str, version: Version) {
+    let [major, minor, build, patch] = version.padded();
+    builder
+        .push(" AND (major, COALESCE(minor, 0), COALESCE(build, 0), COALESCE(patch, 0)) ")
+        .push(operator)
+        .push(" (")
+        .push_bind(major)
+        .push(", ")
+        .push_bind(minor)
+        .push(", ")
+        .push_bind(build)
+        .push(", ")
+        .push_bind(patch)
+        .push(")");
+}
+
+/// Appends the version range of `query` to a `WHERE` clause on
+/// `version_column`. Nothing is appended without bounds.
+pub fn push_version_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    version_column: &str,
+    query: &EventQuery,
+) -> Result<(), AppError> {
+    let min = parse_version(&query.version_min)?;
+    let max = parse_version(&query.version_max)?;
+    if min.is_none() && max.is_none() {
+        return Ok(());
+    }
+    builder
+        .push(" AND ")
+        .push(version_column)
+        .push(" IN (SELECT value FROM telemetry_dim_version WHERE major IS NOT NULL");
+    if let Some(min) = min {
+        push_version_bound(builder, ">=", min);
+    }
+    if let Some(max) = max {
+        push_version_bound(builder, "<=", max);
+    }
+    builder.push(")");
+    Ok(())
+}
+
+/// Appends every filter of `query` except `latest_lines` and paging to a
+/// `WHERE` clause on `telemetry_events`.
+fn push_filters(builder: &mut QueryBuilder<'_, Postgres>, query: &EventQuery) -> Result<(), AppError> {
+    for (column, value) in [
+        ("metric_name", &query.metric_name),
+        ("channel", &query.channel),
+        ("platform", &query.platform),
+        ("country_code", &query.country_code),
+    ] {
+        if let Some(value) = value {
+            builder.push(" AND ").push(column).push(" = ").push_bind(value.clone());
+        }
+    }
+    push_version_filters(builder, "version", query)?;
+    if let Some(from) = query.from {
+        builder.push(" AND received_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND received_at < ").push_bind(to);
+    }
+    Ok(())
+}
+
+/// Newest first.
+pub async fn query_events(pool: &DBPool, query: &EventQuery) -> Result<Vec<StoredEvent>, AppError> {
+    if query.latest_lines.is_some_and(|n| n < 1) {
+        return Err(AppError::BadRequest("latest_lines must be positive".to_string()));
+    }
+    let mut builder = QueryBuilder::new(
+        "SELECT id, cadence, channel, country_code, metric_name, metric_value, \
+         platform, version, woi, wos, yoi, yos, received_at \
+         FROM telemetry_events WHERE TRUE",
+    );
+    push_filters(&mut builder, query)?;
+    if let Some(latest) = query.latest_lines {
+        // Release lines of the matching rows only, so other channels or
+        // metrics can't crowd them out.
+        builder.push(
+            " AND version IN (SELECT value FROM telemetry_dim_version WHERE (major, COALESCE(minor, 0)) IN (\
+             SELECT DISTINCT major, COALESCE(minor, 0) AS minor FROM telemetry_dim_version \
+             WHERE major IS NOT NULL AND value IN (SELECT version FROM telemetry_events WHERE TRUE",
+        );
+        push_filters(&mut builder, query)?;
+        builder
+            .push(") ORDER BY major DESC, minor DESC LIMIT ")
+            .push_bind(latest)
+            .push("))");
+    }
+    if let Some(before_id) = query.before_id {
+        builder.push(" AND id < ").push_bind(before_id);
+    }
+    builder
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE));
+    builder
+        .build_query_as()
+        .fetch_all(&pool.inner_pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn get_events(pool: web::Data<DBPool>, query: web::Query<EventQuery>) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(query_events(&pool, &query).await?))
+}
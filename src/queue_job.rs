@@ -1,17 +1,120 @@
 use actix::Addr;
-use actix_web::{web, HttpResponse, Responder};
-use crate::payload::MyPayload;
+use actix_web::{mime, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use crate::amqp::AmqpPublisher;
+use crate::dedup::{DedupStore, MAX_KEY_LEN};
+use crate::error::AppError;
+use crate::client_ip::client_ip;
+use crate::geoip::GeoIp;
+use crate::registry::MetricRegistry;
+use crate::temporal::TemporalPolicy;
+use crate::metrics;
+use crate::models::DBPool;
+use crate::payload::{ExtraKeys, MyPayload};
+use crate::quarantine::{self, Rejection, COUNTRY_MISMATCH};
 use crate::worker::{DeliveryMessage, ActorWorker};
+use chrono::Utc;
+
+/// Destination for accepted events: the in-process worker, or a RabbitMQ
+/// exchange drained by a separate consumer process.
+pub enum EventQueue {
+    Worker(Addr<ActorWorker>),
+    Amqp(Box<AmqpPublisher>),
+}
+
+impl EventQueue {
+    pub async fn push(&self, payload: MyPayload) -> Result<(), AppError> {
+        match self {
+            EventQueue::Worker(addr) => addr
+                .send(DeliveryMessage(payload))
+                .await
+                .map_err(|e| AppError::QueueError(e.to_string()))?
+                .map_err(AppError::from),
+            EventQueue::Amqp(publisher) => publisher.publish(&payload).await,
+        }
+    }
+}
+
+/// Header carrying the idempotency key, takes precedence over the payload field.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Largest accepted submission body.
+pub const MAX_PAYLOAD_SIZE: usize = 32 * 1024;
 
 pub async fn queue_job(
-    ctx: web::Data<Addr<ActorWorker>>,
-    item: web::Json<MyPayload>,
+    req: HttpRequest,
+    ctx: web::Data<EventQueue>,
+    dedup: Option<web::Data<DedupStore>>,
+    geoip: Option<web::Data<GeoIp>>,
+    registry: Option<web::Data<MetricRegistry>>,
+    pool: Option<web::Data<DBPool>>,
+    body: web::Bytes,
 ) -> impl Responder {
-    let payload = item.into_inner();
-    let addr = ctx.get_ref();
-    let data = DeliveryMessage { 0: payload };
-    match addr.send(data).await {
-        Ok(_) => HttpResponse::Ok().json("Job queued"),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to queue job")
-    }
-}
\ No newline at end of file
+    let is_json = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .is_some_and(|mime| mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON));
+    if !is_json {
+        return HttpResponse::UnsupportedMediaType().body("Expected application/json");
+    }
+    let pool = pool.as_ref().map(|pool| pool.get_ref());
+    let mut payload = match serde_json::from_slice::<MyPayload>(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let rejection = Rejection::new("invalid_json", e.to_string());
+            quarantine::quarantine(pool, &req, &rejection, &body).await;
+            return HttpResponse::BadRequest().body(format!("Invalid payload: {}", e));
+        }
+    };
+    ExtraKeys::from_env().retain(&mut payload);
+    if let Some(geoip) = &geoip {
+        // The address is only looked up, never kept.
+        if let Err(e) = geoip.apply(&mut payload, client_ip(&req)) {
+            let rejection = Rejection::new(COUNTRY_MISMATCH, e.to_string());
+            quarantine::quarantine(pool, &req, &rejection, &body).await;
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+    }
+    if let Some(header) = req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        match header.to_str() {
+            Ok(key) => payload.idempotency_key = Some(key.to_string()),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid idempotency key"),
+        }
+    }
+    if let Err(rejection) = quarantine::validate(
+        &payload,
+        registry.as_ref().map(|r| r.get_ref()),
+        TemporalPolicy::from_env(),
+        Utc::now().date_naive(),
+    ) {
+        // The payload as validated, so a resubmission keeps the GeoIP country.
+        let validated = serde_json::to_vec(&payload).unwrap_or(body.to_vec());
+        quarantine::quarantine(pool, &req, &rejection, &validated).await;
+        return HttpResponse::BadRequest().body(format!("{}: {}", payload.metric_name, rejection.detail));
+    }
+    let key = payload.idempotency_key.clone().filter(|key| !key.is_empty());
+    if key.as_ref().is_some_and(|key| key.len() > MAX_KEY_LEN) {
+        return HttpResponse::BadRequest().body("Invalid idempotency key");
+    }
+    payload.idempotency_key = key.clone();
+
+    if let (Some(dedup), Some(key)) = (&dedup, &key)
+        && !dedup.insert(key)
+    {
+        metrics::increment("ingested_events_total", &[("result", "duplicate")]);
+        return HttpResponse::Ok().json("Duplicate job ignored");
+    }
+    match ctx.push(payload).await {
+        Ok(()) => {
+            metrics::increment("ingested_events_total", &[("result", "queued")]);
+            HttpResponse::Ok().json("Job queued")
+        }
+        Err(e) => {
+            log::error!("Failed to queue job: {}", e);
+            metrics::increment("ingested_events_total", &[("result", "failed")]);
+            if let (Some(dedup), Some(key)) = (&dedup, &key) {
+                dedup.remove(key);
+            }
+            HttpResponse::InternalServerError().body("Failed to queue job")
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Token-bucket rate limiting middleware.
+//!
+//! Limits are configured per route with `RATE_LIMIT_<ROUTE>=<key>:<rate>:<burst>`,
+//! e.g. `RATE_LIMIT_INGEST=ip:20:100` allows each client address 20 requests
+//! per second with bursts of up to 100. The key is one of `ip`, `service_key`
+//! or `channel`. Client addresses come from [`client_ip`], and requests without
+//! a valid service key are limited by address under `service_key`.
+//! `channel` buckets exist only for the channels listed in
+//! `RATE_LIMIT_CHANNELS`; requests for any other path share one `other` bucket.
+//! Unconfigured routes are not limited. Rejected requests get 429 with
+//! `Retry-After` and are counted in `rate_limited_requests_total`.
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use lru::LruCache;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::future::{ready, Future, Ready};
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::auth::{has_service_key, service_key_from_env};
+use crate::client_ip::{client_ip, ip_digest};
+use crate::metrics;
+
+const RATE_LIMIT_ENV_PREFIX: &str = "RATE_LIMIT_";
+const RATE_LIMIT_CHANNELS_ENV_KEY: &str = "RATE_LIMIT_CHANNELS";
+const RATE_LIMIT_CHANNELS_DEFAULT: &str = "release,beta,nightly";
+/// Bucket shared by requests for channels not in `RATE_LIMIT_CHANNELS`.
+const OTHER_CHANNEL: &str = "other";
+/// Buckets kept per route; the least recently used one is dropped beyond this.
+const MAX_BUCKETS: usize = 10_000;
+
+/// What requests are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    Ip,
+    ServiceKey,
+    Channel,
+}
+
+impl RateLimitKey {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitKey::Ip => "ip",
+            RateLimitKey::ServiceKey => "service_key",
+            RateLimitKey::Channel => "channel",
+        }
+    }
+
+    fn extract(&self, req: &HttpRequest, service_key: Option<&str>) -> String {
+        // Buckets are keyed by a digest so raw addresses are never retained.
+        let ip = || ip_digest(client_ip(req));
+        match self {
+            RateLimitKey::Ip => ip(),
+            RateLimitKey::ServiceKey => match service_key {
+                Some(key) if has_service_key(req, key) => "service_key".to_string(),
+                _ => format!("anonymous:{}", ip()),
+            },
+            RateLimitKey::Channel => {
+                let channel = req.match_info().get("channel").unwrap_or("");
+                // The channel is part of the path, so clients must not be able to mint buckets.
+                if known_channels().contains(channel) {
+                    channel.to_string()
+                } else {
+                    OTHER_CHANNEL.to_string()
+                }
+            }
+        }
+    }
+}
+
+fn known_channels() -> &'static HashSet<String> {
+    static CHANNELS: OnceLock<HashSet<String>> = OnceLock::new();
+    CHANNELS.get_or_init(|| {
+        env::var(RATE_LIMIT_CHANNELS_ENV_KEY)
+            .unwrap_or(RATE_LIMIT_CHANNELS_DEFAULT.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|channel| !channel.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+impl FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(RateLimitKey::Ip),
+            "service_key" => Ok(RateLimitKey::ServiceKey),
+            "channel" => Ok(RateLimitKey::Channel),
+            other => Err(format!("unknown rate limit key: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    pub key: RateLimitKey,
+    /// Tokens added per second.
+    pub rate: f64,
+    /// Bucket size, the largest burst allowed.
+    pub burst: f64,
+}
+
+impl FromStr for RateLimitConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [key, rate, burst] = parts.as_slice() else {
+            return Err(format!("expected <key>:<rate>:<burst>, got {}", s));
+        };
+        let number = |value: &str| match f64::from_str(value) {
+            Ok(n) if n > 0.0 => Ok(n),
+            _ => Err(format!("expected a positive number, got {}", value)),
+        };
+        Ok(RateLimitConfig {
+            key: RateLimitKey::from_str(key)?,
+            rate: number(rate)?,
+            burst: number(burst)?,
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+type Buckets = Arc<Mutex<LruCache<String, Bucket>>>;
+
+fn new_buckets() -> Buckets {
+    Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(MAX_BUCKETS).unwrap())))
+}
+
+/// Takes a token from `key`'s bucket, or returns the seconds until one is available.
+fn take(buckets: &Buckets, config: &RateLimitConfig, key: String) -> Result<(), u64> {
+    let now = Instant::now();
+    let mut buckets = buckets.lock().unwrap();
+    let bucket = buckets.get_or_insert_mut(key, || Bucket {
+        tokens: config.burst,
+        updated: now,
+    });
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * config.rate).min(config.burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(((1.0 - bucket.tokens) / config.rate).ceil().max(1.0) as u64)
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    route: &'static str,
+    config: Option<RateLimitConfig>,
+    service_key: Option<Arc<String>>,
+    buckets: Buckets,
+}
+
+impl RateLimiter {
+    pub fn new(route: &'static str, config: Option<RateLimitConfig>) -> Self {
+        Self {
+            route,
+            config,
+            service_key: None,
+            buckets: new_buckets(),
+        }
+    }
+
+    /// Sets the key `service_key` limits accept, as checked by `AuthMiddleware`.
+    pub fn with_service_key(mut self, service_key: impl Into<String>) -> Self {
+        self.service_key = Some(Arc::new(service_key.into()));
+        self
+    }
+
+    /// Reads `RATE_LIMIT_<ROUTE>` and `BRAVE_SERVICE_KEY`. Buckets are shared
+    /// by all workers limiting the same route.
+    pub fn from_env(route: &'static str) -> Self {
+        Self::from_env_or(route, None)
+    }
+
+    /// Like [`RateLimiter::from_env`], limiting with `default` when
+    /// `RATE_LIMIT_<ROUTE>` is unset.
+    pub fn from_env_or(route: &'static str, default: Option<&str>) -> Self {
+        static SHARED: OnceLock<Mutex<HashMap<&'static str, Buckets>>> = OnceLock::new();
+        let env_key = format!("{}{}", RATE_LIMIT_ENV_PREFIX, route.to_uppercase());
+        let config = env::var(&env_key)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .or(default.map(str::to_string))
+            .map(|v| RateLimitConfig::from_str(&v).unwrap_or_else(|e| panic!("{} is invalid: {}", env_key, e)));
+        let buckets = SHARED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry(route)
+            .or_insert_with(new_buckets)
+            .clone();
+        Self {
+            route,
+            config,
+            service_key: service_key_from_env().map(Arc::new),
+            buckets,
+        }
+    }
+
+    /// Takes a token for `req`, or returns the seconds until one is available.
+    pub fn check(&self, req: &HttpRequest) -> Result<(), u64> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        take(&self.buckets, config, config.key.extract(req, self.service_key.as_deref().map(String::as_str)))
+            .inspect_err(|_| {
+                metrics::increment(
+                    "rate_limited_requests_total",
+                    &[("route", self.route), ("key", config.key.as_str())],
+                )
+            })
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimiterService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterService {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimiterService<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Err(retry_after) = self.limiter.check(req.request()) {
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after))
+                .body("Too many requests");
+            return Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) });
+        }
+
+        let service = self.service.clone();
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
@@ -0,0 +1,178 @@
+//! Registry of the metrics the server accepts.
+//!
+//! Loaded from the JSON file in `METRIC_REGISTRY_PATH`, a list of
+//! [`MetricDefinition`]s. A name ending in `.*` covers every metric with that
+//! prefix. Submissions for unknown metrics are rejected with 400. Expired
+//! metrics, values outside the declared bucket range and undeclared cadences
+//! are rejected too when `METRIC_REGISTRY_MODE` is `reject` (the default), or
+//! only logged and counted when it is `warn`. Without a registry file nothing
+//! is checked.
+
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::AppError;
+use crate::payload::MyPayload;
+
+const METRIC_REGISTRY_PATH_ENV_KEY: &str = "METRIC_REGISTRY_PATH";
+const METRIC_REGISTRY_MODE_ENV_KEY: &str = "METRIC_REGISTRY_MODE";
+const METRIC_REGISTRY_MODE_DEFAULT: &str = "reject";
+const PREFIX_WILDCARD: &str = ".*";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricDefinition {
+    pub name: String,
+    /// Lowest accepted bucket.
+    pub min_value: i32,
+    /// Highest accepted bucket.
+    pub max_value: i32,
+    /// Accepted cadences, e.g. `typical`, `express`, `slow`.
+    pub cadences: Vec<String>,
+    pub owner: String,
+    /// Last day submissions are accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Why a submission does not match the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetricViolation {
+    UnknownMetric,
+    Expired(NaiveDate),
+    ValueOutOfRange { min: i32, max: i32 },
+    CadenceNotAllowed,
+}
+
+impl MetricViolation {
+    /// Short label used in metrics and stored reasons.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MetricViolation::UnknownMetric => "unknown_metric",
+            MetricViolation::Expired(_) => "expired_metric",
+            MetricViolation::ValueOutOfRange { .. } => "value_out_of_range",
+            MetricViolation::CadenceNotAllowed => "cadence_not_allowed",
+        }
+    }
+}
+
+impl fmt::Display for MetricViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricViolation::UnknownMetric => write!(f, "metric is not registered"),
+            MetricViolation::Expired(date) => write!(f, "metric expired on {}", date),
+            MetricViolation::ValueOutOfRange { min, max } => {
+                write!(f, "metric_value must be between {} and {}", min, max)
+            }
+            MetricViolation::CadenceNotAllowed => write!(f, "cadence is not allowed for this metric"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryMode {
+    Reject,
+    Warn,
+}
+
+impl FromStr for RegistryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(RegistryMode::Reject),
+            "warn" => Ok(RegistryMode::Warn),
+            other => Err(format!("unknown registry mode: {}", other)),
+        }
+    }
+}
+
+pub struct MetricRegistry {
+    metrics: HashMap<String, MetricDefinition>,
+    pub mode: RegistryMode,
+}
+
+impl MetricRegistry {
+    pub fn new(definitions: Vec<MetricDefinition>, mode: RegistryMode) -> Result<Self, AppError> {
+        let mut metrics = HashMap::new();
+        for definition in definitions {
+            if definition.min_value > definition.max_value {
+                return Err(AppError::BadRequest(format!("{}: min_value exceeds max_value", definition.name)));
+            }
+            if metrics.insert(definition.name.clone(), definition.clone()).is_some() {
+                return Err(AppError::BadRequest(format!("{} is registered twice", definition.name)));
+            }
+        }
+        Ok(Self { metrics, mode })
+    }
+
+    pub fn load(path: &Path, mode: RegistryMode) -> Result<Self, AppError> {
+        let definitions: Vec<MetricDefinition> = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| AppError::SerdeError(format!("{}: {}", path.display(), e)))?;
+        Self::new(definitions, mode)
+    }
+
+    /// Returns `None` when `METRIC_REGISTRY_PATH` is unset.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Some(path) = env::var(METRIC_REGISTRY_PATH_ENV_KEY).ok().filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        let mode = RegistryMode::from_str(
+            &env::var(METRIC_REGISTRY_MODE_ENV_KEY).unwrap_or(METRIC_REGISTRY_MODE_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|e| panic!("{} is invalid: {}", METRIC_REGISTRY_MODE_ENV_KEY, e));
+        Self::load(Path::new(&path), mode).map(Some)
+    }
+
+    /// Exact definition of `name`, else the longest matching `prefix.*` one.
+    pub fn get(&self, name: &str) -> Option<&MetricDefinition> {
+        self.metrics.get(name).or_else(|| {
+            self.metrics
+                .iter()
+                .filter_map(|(key, definition)| {
+                    let prefix = key.strip_suffix(PREFIX_WILDCARD)?;
+                    name.strip_prefix(prefix)?.starts_with('.').then_some((prefix.len(), definition))
+                })
+                .max_by_key(|(len, _)| *len)
+                .map(|(_, definition)| definition)
+        })
+    }
+
+    pub fn check(&self, payload: &MyPayload, today: NaiveDate) -> Result<(), MetricViolation> {
+        let definition = self.get(&payload.metric_name).ok_or(MetricViolation::UnknownMetric)?;
+        if let Some(expires) = definition.expires
+            && today > expires
+        {
+            return Err(MetricViolation::Expired(expires));
+        }
+        if !(definition.min_value..=definition.max_value).contains(&payload.metric_value) {
+            return Err(MetricViolation::ValueOutOfRange {
+                min: definition.min_value,
+                max: definition.max_value,
+            });
+        }
+        if !definition.cadences.iter().any(|cadence| cadence == &payload.cadence) {
+            return Err(MetricViolation::CadenceNotAllowed);
+        }
+        Ok(())
+    }
+
+    /// All definitions, sorted by name.
+    pub fn definitions(&self) -> Vec<MetricDefinition> {
+        let mut definitions: Vec<_> = self.metrics.values().cloned().collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+}
+
+pub async fn list_metrics(registry: Option<web::Data<MetricRegistry>>) -> HttpResponse {
+    let definitions = registry.map(|registry| registry.definitions()).unwrap_or_default();
+    HttpResponse::Ok().json(definitions)
+}
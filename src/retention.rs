@@ -0,0 +1,189 @@
+//! Weekly retention of install cohorts, from the install (`woi`/`yoi`) and
+//! survey (`wos`/`yos`) weeks of a metric's reports.
+//!
+//! Reports are counted per cohort and week since install in the
+//! `telemetry_retention_weekly` materialized view, which the server
+//! refreshes every `RETENTION_REFRESH_SECS` (0 disables the refresh).
+//! Retention is the share of a cohort's week 0 reports still seen in later
+//! weeks, served at `GET /api/v1/retention`.
+
+use actix::{Actor, AsyncContext, Context};
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::models::DBPool;
+
+const RETENTION_REFRESH_SECS_ENV_KEY: &str = "RETENTION_REFRESH_SECS";
+const RETENTION_REFRESH_SECS_DEFAULT: &str = "3600";
+const DEFAULT_MAX_WEEKS: i32 = 12;
+const MAX_WEEKS_LIMIT: i32 = 104;
+
+pub async fn refresh(pool: &DBPool) -> Result<(), sqlx::Error> {
+    sqlx::query("REFRESH MATERIALIZED VIEW CONCURRENTLY telemetry_retention_weekly")
+        .execute(&pool.inner_pool)
+        .await?;
+    Ok(())
+}
+
+/// Refreshes the retention view on an interval. A refresh still running
+/// when the next one is due is not started twice.
+pub struct RetentionRefresher {
+    pool: Arc<DBPool>,
+    interval: Duration,
+    running: Arc<AtomicBool>,
+}
+
+impl RetentionRefresher {
+    pub fn new(pool: Arc<DBPool>, interval: Duration) -> Self {
+        Self {
+            pool,
+            interval,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns `None` when `RETENTION_REFRESH_SECS` is 0.
+    pub fn from_env(pool: Arc<DBPool>) -> Option<Self> {
+        let secs = u64::from_str(
+            &env::var(RETENTION_REFRESH_SECS_ENV_KEY).unwrap_or(RETENTION_REFRESH_SECS_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|_| panic!("{} must be a non-negative integer", RETENTION_REFRESH_SECS_ENV_KEY));
+        (secs > 0).then(|| Self::new(pool, Duration::from_secs(secs)))
+    }
+
+    fn refresh(&self) {
+        if self.running.swap(true, Ordering::AcqRel) {
+            log::warn!("Previous retention refresh still running, skipping");
+            return;
+        }
+        let pool = self.pool.clone();
+        let running = self.running.clone();
+        actix::spawn(async move {
+            match refresh(&pool).await {
+                Ok(()) => log::debug!("Refreshed retention view"),
+                Err(e) => log::error!("Failed to refresh retention view: {}", e),
+            }
+            running.store(false, Ordering::Release);
+        });
+    }
+}
+
+impl Actor for RetentionRefresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.interval, |refresher, _ctx| refresher.refresh());
+    }
+}
+
+/// One cell of the retention matrix as stored in the view.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct RetentionRow {
+    pub platform: String,
+    pub channel: String,
+    pub install_week: NaiveDate,
+    pub weeks_since_install: i32,
+    pub reports: i64,
+}
+
+/// Reports of one install cohort, indexed by weeks since install.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RetentionCohort {
+    pub platform: String,
+    pub channel: String,
+    /// Monday of the install week.
+    pub install_week: NaiveDate,
+    pub reports: Vec<i64>,
+    /// `reports` relative to week 0, or `None` without week 0 reports.
+    pub retention: Vec<Option<f64>>,
+}
+
+/// Builds one row of `max_weeks + 1` cells per platform, channel and install
+/// week. Cells beyond `max_weeks` are ignored.
+pub fn build_matrix(rows: Vec<RetentionRow>, max_weeks: i32) -> Vec<RetentionCohort> {
+    let width = max_weeks as usize + 1;
+    let mut cohorts: BTreeMap<(String, String, NaiveDate), Vec<i64>> = BTreeMap::new();
+    for row in rows {
+        if !(0..=max_weeks).contains(&row.weeks_since_install) {
+            continue;
+        }
+        let reports = cohorts
+            .entry((row.platform, row.channel, row.install_week))
+            .or_insert_with(|| vec![0; width]);
+        reports[row.weeks_since_install as usize] += row.reports;
+    }
+    cohorts
+        .into_iter()
+        .map(|((platform, channel, install_week), reports)| {
+            let size = reports[0];
+            let retention = reports
+                .iter()
+                .map(|&count| (size > 0).then(|| count as f64 / size as f64))
+                .collect();
+            RetentionCohort {
+                platform,
+                channel,
+                install_week,
+                reports,
+                retention,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RetentionQuery {
+    pub metric_name: String,
+    pub platform: Option<String>,
+    pub channel: Option<String>,
+    /// First install week, inclusive.
+    pub from: Option<NaiveDate>,
+    /// Last install week, inclusive.
+    pub to: Option<NaiveDate>,
+    pub max_weeks: Option<i32>,
+}
+
+pub async fn retention_rows(pool: &DBPool, query: &RetentionQuery, max_weeks: i32) -> Result<Vec<RetentionRow>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT p.value AS platform, c.value AS channel, r.install_week, r.weeks_since_install, r.reports
+        FROM telemetry_retention_weekly r
+        JOIN telemetry_dim_metric_name m ON m.id = r.metric_name_id
+        JOIN telemetry_dim_platform p ON p.id = r.platform_id
+        JOIN telemetry_dim_channel c ON c.id = r.channel_id
+        WHERE m.value = $1
+          AND ($2::text IS NULL OR p.value = $2)
+          AND ($3::text IS NULL OR c.value = $3)
+          AND ($4::date IS NULL OR r.install_week >= $4)
+          AND ($5::date IS NULL OR r.install_week <= $5)
+          AND r.weeks_since_install <= $6
+        "#,
+    )
+    .bind(&query.metric_name)
+    .bind(&query.platform)
+    .bind(&query.channel)
+    .bind(query.from)
+    .bind(query.to)
+    .bind(max_weeks)
+    .fetch_all(&pool.inner_pool)
+    .await
+}
+
+pub async fn get_retention(pool: web::Data<DBPool>, query: web::Query<RetentionQuery>) -> Result<HttpResponse, AppError> {
+    let max_weeks = query.max_weeks.unwrap_or(DEFAULT_MAX_WEEKS);
+    if !(0..=MAX_WEEKS_LIMIT).contains(&max_weeks) {
+        return Err(AppError::BadRequest(format!("max_weeks must be between 0 and {}", MAX_WEEKS_LIMIT)));
+    }
+    let rows = retention_rows(&pool, &query, max_weeks)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(build_matrix(rows, max_weeks)))
+}
@@ -1,11 +1,86 @@
 use actix_web::{web::self};
 use actix_web::dev::HttpServiceFactory;
-use crate::queue_job::queue_job;
-
+use crate::adoption::get_adoption;
+use crate::auth::AuthMiddleware;
+use crate::omaha::import::import_catalog;
+use crate::omaha::packages::{download_package, upload_package};
+use crate::omaha::rollout::{get_rollouts, put_rollout, remove_rollout};
+use crate::omaha::update_check;
+use crate::query::get_events;
+use crate::quarantine::{browse_quarantine, resubmit_quarantine};
+use crate::queue_job::{queue_job, MAX_PAYLOAD_SIZE};
+use crate::ratelimit::RateLimiter;
+use crate::registry::list_metrics;
+use crate::retention::get_retention;
 
+/// Event submission at `/api/v1/{channel}`, next to the admin routes.
+///
+/// Admin routes are matched first, so `import`, `rollouts`, `quarantine`,
+/// `events` and `retention` can never be used as channel names.
 pub fn service_scope() -> impl HttpServiceFactory {
     web::scope("/api/v1")
         // .wrap(AuthMiddleware::new())
-        .route("/{channel}", web::post().to(queue_job))
+        .service(
+            web::resource("/import")
+                .wrap(AuthMiddleware::new())
+                .route(web::get().to(import_catalog))
+                .route(web::post().to(import_catalog)),
+        )
+        .service(
+            web::resource("/crx/{appid}/{version}/{name}")
+                .wrap(AuthMiddleware::new())
+                .route(web::put().to(upload_package)),
+        )
+        .service(
+            web::scope("/rollouts")
+                .wrap(AuthMiddleware::new())
+                .route("", web::get().to(get_rollouts))
+                .route("", web::put().to(put_rollout))
+                .route("/{appid}/{channel}/{platform}", web::delete().to(remove_rollout)),
+        )
+        .service(
+            web::scope("/quarantine")
+                .wrap(AuthMiddleware::new())
+                .route("", web::get().to(browse_quarantine))
+                .route("/resubmit", web::post().to(resubmit_quarantine)),
+        )
+        .service(
+            web::resource("/events")
+                .wrap(AuthMiddleware::new())
+                .route(web::get().to(get_events)),
+        )
+        .service(
+            web::resource("/retention")
+                .wrap(AuthMiddleware::new())
+                .route(web::get().to(get_retention)),
+        )
+        .service(
+            web::resource("/reports/adoption")
+                .wrap(AuthMiddleware::new())
+                .route(web::get().to(get_adoption)),
+        )
+        .route("/registry/metrics", web::get().to(list_metrics))
+        .service(
+            web::resource("/{channel}")
+                .app_data(web::PayloadConfig::new(MAX_PAYLOAD_SIZE))
+                .wrap(RateLimiter::from_env("ingest"))
+                .route(web::post().to(queue_job)),
+        )
         
-}
\ No newline at end of file
+}
+
+/// Omaha 3.1 JSON endpoint queried by the component updater.
+pub fn update_scope() -> impl HttpServiceFactory {
+    web::scope("/service/update2")
+        .service(
+            web::resource("/json")
+                .wrap(RateLimiter::from_env("update"))
+                .route(web::post().to(update_check)),
+        )
+}
+
+/// Self-hosted CRX packages, referenced from update-check codebases.
+pub fn crx_scope() -> impl HttpServiceFactory {
+    web::scope("/crx")
+        .route("/{appid}/{version}/{name}", web::get().to(download_package))
+}
@@ -0,0 +1,293 @@
+//! Destinations for batches of accepted events.
+//!
+//! `EVENT_SINKS` is a comma separated list of `postgres`, `stdout` and
+//! `ndjson:/path/to/file`, defaulting to `postgres`. With more than one
+//! sink every batch goes to all of them. A batch a sink fails to write is
+//! spooled to that sink's own directory under `SINK_SPOOL_DIR` (default
+//! `sink-spool`) and retried before its next batch, so the batch still
+//! counts as written and the other sinks don't get it twice. Only a batch
+//! that can't be spooled either fails, and is then retried everywhere.
+
+use futures_util::future::{join_all, BoxFuture};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::error::AppError;
+use crate::metrics;
+use crate::models::DBPool;
+use crate::payload::MyPayload;
+use crate::telemetry_event::insert_events;
+use crate::wal;
+
+const EVENT_SINKS_ENV_KEY: &str = "EVENT_SINKS";
+const EVENT_SINKS_DEFAULT: &str = "postgres";
+const SINK_SPOOL_DIR_ENV_KEY: &str = "SINK_SPOOL_DIR";
+const SINK_SPOOL_DIR_DEFAULT: &str = "sink-spool";
+
+pub trait EventSink: Send + Sync {
+    /// Label used in logs and metrics.
+    fn name(&self) -> &str;
+
+    /// Writes one batch. Either the whole batch is written or an error is
+    /// returned.
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>>;
+}
+
+/// Inserts into `telemetry_events` through [`insert_events`].
+pub struct PostgresSink {
+    pool: Arc<DBPool>,
+}
+
+impl PostgresSink {
+    pub fn new(pool: Arc<DBPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl EventSink for PostgresSink {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            insert_events(self.pool.clone(), events.to_vec())
+                .await
+                .map_err(|e| AppError::DatabaseError(e.to_string()))
+        })
+    }
+}
+
+fn to_ndjson(events: &[MyPayload]) -> Result<Vec<u8>, AppError> {
+    let mut buf = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut buf, event).map_err(|e| AppError::SerdeError(e.to_string()))?;
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+/// Runs a blocking write on the blocking thread pool, so a slow disk or a
+/// full pipe doesn't stall the arbiter that called the sink.
+async fn write_blocking(write: impl FnOnce() -> io::Result<()> + Send + 'static) -> Result<(), AppError> {
+    tokio::task::spawn_blocking(write).await.map_err(io::Error::other)??;
+    Ok(())
+}
+
+/// Appends one JSON line per event to a file.
+pub struct NdjsonFileSink {
+    name: String,
+    file: Arc<Mutex<File>>,
+}
+
+impl NdjsonFileSink {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            name: format!("ndjson:{}", path.display()),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+}
+
+impl EventSink for NdjsonFileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let buf = to_ndjson(events)?;
+            let file = self.file.clone();
+            write_blocking(move || {
+                let mut file = file.lock().unwrap();
+                file.write_all(&buf)?;
+                file.flush()
+            })
+            .await
+        })
+    }
+}
+
+/// Prints one JSON line per event, for running without a database.
+pub struct StdoutSink;
+
+impl EventSink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let buf = to_ndjson(events)?;
+            write_blocking(move || {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&buf)?;
+                stdout.flush()
+            })
+            .await
+        })
+    }
+}
+
+/// A sink of a [`FanOut`] and the directory holding the batches it failed
+/// to write.
+struct Member {
+    sink: Box<dyn EventSink>,
+    spool: PathBuf,
+    /// Held for a whole write, so concurrent batches don't replay the same
+    /// spooled segment twice.
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl Member {
+    /// Writes the spooled batches and then `events`, oldest first. Once a
+    /// write fails `events` is spooled instead. Returns the metrics outcome.
+    async fn write(&self, events: &[MyPayload]) -> Result<&'static str, AppError> {
+        let _guard = self.lock.lock().await;
+        let written = match wal::replay(&self.spool, self.sink.as_ref()).await {
+            Ok(_) => self.sink.write(events).await,
+            Err(e) => Err(e),
+        };
+        let Err(e) = written else {
+            return Ok("ok");
+        };
+        log::error!("Sink {} failed to write {} events, spooling them: {}", self.sink.name(), events.len(), e);
+        let spool = self.spool.clone();
+        let events = events.to_vec();
+        write_blocking(move || wal::write_segment(&spool, &events).map(drop)).await?;
+        Ok("spooled")
+    }
+}
+
+/// Writes every batch to all of its sinks concurrently, spooling it for the
+/// sinks that fail.
+pub struct FanOut {
+    members: Vec<Member>,
+}
+
+impl FanOut {
+    /// Spools the batches of each sink to a directory under `spool_dir`
+    /// named after the sink.
+    pub fn new(sinks: Vec<Box<dyn EventSink>>, spool_dir: &Path) -> Self {
+        let members = sinks
+            .into_iter()
+            .map(|sink| {
+                let dir_name: String = sink
+                    .name()
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                Member { spool: spool_dir.join(dir_name), sink, lock: Default::default() }
+            })
+            .collect();
+        Self { members }
+    }
+}
+
+impl EventSink for FanOut {
+    fn name(&self) -> &str {
+        "fan_out"
+    }
+
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let results = join_all(self.members.iter().map(|member| member.write(events))).await;
+            let mut failed = Vec::new();
+            for (member, result) in self.members.iter().zip(results) {
+                let name = member.sink.name();
+                let outcome = result.unwrap_or_else(|e| {
+                    log::error!("Sink {} could not spool {} events: {}", name, events.len(), e);
+                    failed.push(name);
+                    "error"
+                });
+                metrics::increment("sink_writes_total", &[("sink", name), ("result", outcome)]);
+            }
+            if failed.is_empty() {
+                Ok(())
+            } else {
+                Err(AppError::QueueError(format!("sinks failed: {}", failed.join(", "))))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkConfig {
+    Postgres,
+    Stdout,
+    Ndjson(PathBuf),
+}
+
+impl FromStr for SinkConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(SinkConfig::Postgres),
+            "stdout" => Ok(SinkConfig::Stdout),
+            other => match other.strip_prefix("ndjson:") {
+                Some(path) if !path.is_empty() => Ok(SinkConfig::Ndjson(PathBuf::from(path))),
+                _ => Err(format!("unknown event sink: {}", other)),
+            },
+        }
+    }
+}
+
+impl SinkConfig {
+    /// Parses a comma separated list of sinks.
+    pub fn parse_list(value: &str) -> Result<Vec<SinkConfig>, String> {
+        let sinks = value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(SinkConfig::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if sinks.is_empty() {
+            return Err("at least one event sink is required".to_string());
+        }
+        Ok(sinks)
+    }
+
+    pub fn from_env() -> Vec<SinkConfig> {
+        let value = env::var(EVENT_SINKS_ENV_KEY).unwrap_or(EVENT_SINKS_DEFAULT.to_string());
+        Self::parse_list(&value).unwrap_or_else(|e| panic!("Invalid {}: {}", EVENT_SINKS_ENV_KEY, e))
+    }
+
+    pub fn uses_postgres(configs: &[SinkConfig]) -> bool {
+        configs.contains(&SinkConfig::Postgres)
+    }
+
+    fn open(&self, pool: Option<&Arc<DBPool>>) -> io::Result<Box<dyn EventSink>> {
+        Ok(match self {
+            SinkConfig::Postgres => {
+                let pool = pool.ok_or_else(|| io::Error::other("the postgres sink needs a database"))?;
+                Box::new(PostgresSink::new(pool.clone()))
+            }
+            SinkConfig::Stdout => Box::new(StdoutSink),
+            SinkConfig::Ndjson(path) => Box::new(NdjsonFileSink::open(path)?),
+        })
+    }
+}
+
+pub fn spool_dir_from_env() -> PathBuf {
+    PathBuf::from(env::var(SINK_SPOOL_DIR_ENV_KEY).unwrap_or(SINK_SPOOL_DIR_DEFAULT.to_string()))
+}
+
+/// Opens the configured sinks, wrapped in a [`FanOut`] when there are
+/// several. `pool` is only needed by the `postgres` sink.
+pub fn build_sink(configs: &[SinkConfig], pool: Option<&Arc<DBPool>>) -> io::Result<Arc<dyn EventSink>> {
+    let mut sinks = configs
+        .iter()
+        .map(|config| config.open(pool))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(if sinks.len() == 1 {
+        Arc::from(sinks.pop().unwrap())
+    } else {
+        Arc::new(FanOut::new(sinks, &spool_dir_from_env()))
+    })
+}
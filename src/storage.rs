@@ -0,0 +1,41 @@
+//! Object storage locations given as URLs.
+//!
+//! `s3://bucket/prefix` targets an S3-compatible service. Credentials and
+//! region come from the usual `AWS_*` variables and `S3_ENDPOINT` points the
+//! client at localstack, MinIO or another non-AWS endpoint.
+//! `file:///some/dir` stores objects on the local filesystem instead.
+
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use std::env;
+use std::sync::Arc;
+
+use crate::error::AppError;
+
+const S3_ENDPOINT_ENV_KEY: &str = "S3_ENDPOINT";
+
+/// Builds a store for `url` and returns it with the key prefix to use in it.
+pub fn store_from_url(url: &str) -> Result<(Arc<dyn ObjectStore>, Path), AppError> {
+    if let Some(rest) = url.strip_prefix("s3://") {
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Ok(endpoint) = env::var(S3_ENDPOINT_ENV_KEY) {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+        let store = builder
+            .build()
+            .map_err(|e| AppError::StorageError(e.to_string()))?;
+        Ok((Arc::new(store), Path::from(prefix)))
+    } else if let Some(dir) = url.strip_prefix("file://") {
+        std::fs::create_dir_all(dir)?;
+        let store = LocalFileSystem::new_with_prefix(dir)
+            .map_err(|e| AppError::StorageError(e.to_string()))?;
+        Ok((Arc::new(store), Path::default()))
+    } else {
+        Err(AppError::StorageError(format!("unsupported storage url: {}", url)))
+    }
+}
@@ -1,49 +1,171 @@
-use std::sync::Arc;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use sqlx::PgConnection;
+use crate::dimensions::{DimensionIds, Resolved};
 use crate::models::DBPool;
 use crate::payload::MyPayload;
 
+const INSERT_MODE_ENV_KEY: &str = "INSERT_MODE";
+const INSERT_MODE_DEFAULT: &str = "unnest";
+const EVENT_KEY_RETENTION_HOURS_ENV_KEY: &str = "EVENT_KEY_RETENTION_HOURS";
+const EVENT_KEY_RETENTION_HOURS_DEFAULT: &str = "168";
+
+/// Signature, flags field and header extension length of a binary COPY stream.
+const COPY_BINARY_HEADER: &[u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+const COPY_BINARY_TRAILER: i16 = -1;
+const COPY_COLUMN_COUNT: i16 = 12;
+/// Field length marking a NULL value.
+const COPY_NULL: i32 = -1;
+/// Version byte preceding the text of a binary `jsonb` value.
+const JSONB_BINARY_VERSION: u8 = 1;
+
+/// How batches are written to `telemetry_events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertMode {
+    /// `INSERT ... SELECT FROM UNNEST` with one array bind per column.
+    Unnest,
+    /// `COPY ... FROM STDIN` in binary format, faster for large batches.
+    Copy,
+}
+
+impl FromStr for InsertMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unnest" => Ok(InsertMode::Unnest),
+            "copy" => Ok(InsertMode::Copy),
+            other => Err(format!("unknown insert mode: {}", other)),
+        }
+    }
+}
+
+impl InsertMode {
+    pub fn from_env() -> Self {
+        static MODE: OnceLock<InsertMode> = OnceLock::new();
+        *MODE.get_or_init(|| {
+            InsertMode::from_str(
+                &env::var(INSERT_MODE_ENV_KEY).unwrap_or(INSERT_MODE_DEFAULT.to_string()),
+            )
+            .unwrap_or_else(|e| panic!("{} is invalid: {}", INSERT_MODE_ENV_KEY, e))
+        })
+    }
+}
+
+/// How long idempotency keys are kept in `telemetry_event_keys`.
+fn event_key_retention_hours() -> i32 {
+    static HOURS: OnceLock<i32> = OnceLock::new();
+    *HOURS.get_or_init(|| {
+        i32::from_str(
+            &env::var(EVENT_KEY_RETENTION_HOURS_ENV_KEY).unwrap_or(EVENT_KEY_RETENTION_HOURS_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|_| panic!("{} must be a positive integer", EVENT_KEY_RETENTION_HOURS_ENV_KEY))
+    })
+}
+
+/// Writes a batch using the writer selected by `INSERT_MODE`. Events whose
+/// idempotency key was already inserted are dropped, as are repeats of a key
+/// within the batch.
 pub async fn insert_events(
     pool: Arc<DBPool>,
     events: Vec<MyPayload>,
+) -> Result<(), sqlx::Error> {
+    match InsertMode::from_env() {
+        InsertMode::Unnest => insert_events_unnest(pool, events).await,
+        InsertMode::Copy => insert_events_copy(pool, events).await,
+    }
+}
+
+pub async fn insert_events_unnest(
+    pool: Arc<DBPool>,
+    events: Vec<MyPayload>,
 ) -> Result<(), sqlx::Error> {
     if events.is_empty() {
         return Ok(());
     }
 
-    // เตรียมข้อมูลแต่ละ column เป็น Vec
-    let cadence: Vec<&str> = events.iter().map(|e| e.cadence.as_str()).collect();
-    let channel: Vec<&str> = events.iter().map(|e| e.channel.as_str()).collect();
-    let country_code: Vec<&str> = events.iter().map(|e| e.country_code.as_str()).collect();
-    let metric_name: Vec<&str> = events.iter().map(|e| e.metric_name.as_str()).collect();
-    let metric_value: Vec<f64> = events.iter().map(|e| e.metric_value as f64).collect();
-    let platform: Vec<&str> = events.iter().map(|e| e.platform.as_str()).collect();
-    let version: Vec<&str> = events.iter().map(|e| e.version.as_str()).collect();
-    let woi: Vec<i32> = events.iter().map(|e| e.woi as i32).collect();
-    let wos: Vec<i32> = events.iter().map(|e| e.wos.unwrap_or(0) as i32).collect();
-    let yoi: Vec<i32> = events.iter().map(|e| e.yoi as i32).collect();
-    let yos: Vec<i32> = events.iter().map(|e| e.yos as i32).collect();
     let mut transaction = pool.inner_pool.begin().await?;
+    let resolved = write_unnest(&pool, &mut transaction, &events).await?;
+    transaction.commit().await?;
+    pool.dimensions.remember(resolved);
+    Ok(())
+}
+
+/// Inserts `events` through UNNEST on `conn`, which the caller commits.
+/// The returned dimension ids are remembered once it has.
+async fn write_unnest(pool: &DBPool, conn: &mut PgConnection, events: &[MyPayload]) -> Result<Resolved, sqlx::Error> {
+    let resolved = pool.dimensions.resolve(&mut *conn, events).await?;
+    let ids = &resolved.ids;
+
+    // เตรียมข้อมูลแต่ละ column เป็น Vec
+    let cadence: Vec<i32> = ids.iter().map(|ids| ids.cadence).collect();
+    let channel: Vec<i32> = ids.iter().map(|ids| ids.channel).collect();
+    let country_code: Vec<i32> = ids.iter().map(|ids| ids.country_code).collect();
+    let metric_name: Vec<i32> = ids.iter().map(|ids| ids.metric_name).collect();
+    let metric_value: Vec<i32> = events.iter().map(|e| e.metric_value).collect();
+    let platform: Vec<i32> = ids.iter().map(|ids| ids.platform).collect();
+    let version: Vec<i32> = ids.iter().map(|ids| ids.version).collect();
+    let woi: Vec<Option<i16>> = events.iter().map(|e| e.woi).collect();
+    let wos: Vec<Option<i16>> = events.iter().map(|e| e.wos).collect();
+    let yoi: Vec<Option<i16>> = events.iter().map(|e| e.yoi).collect();
+    let yos: Vec<i16> = events.iter().map(|e| e.yos).collect();
+    let idempotency_key: Vec<Option<&str>> = events.iter().map(|e| e.idempotency_key.as_deref()).collect();
+    let extra: Vec<Option<String>> = events.iter().map(extra_json).collect();
+    let keyed = idempotency_key.iter().any(Option::is_some);
 
-    sqlx::query(
+    if keyed {
+        sqlx::query("DELETE FROM telemetry_event_keys WHERE received_at < now() - make_interval(hours => $1)")
+            .bind(event_key_retention_hours())
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    // Keys are claimed in telemetry_event_keys first; only rows without a key
+    // or with a freshly claimed one are inserted.
+    let result = sqlx::query(
         r#"
-        INSERT INTO telemetry_events (
-            cadence, channel, country_code, metric_name, metric_value,
-            platform, version, woi, wos, yoi, yos
+        WITH input AS (
+            SELECT *
+            FROM UNNEST(
+                $1::int4[],      -- cadence_id
+                $2::int4[],      -- channel_id
+                $3::int4[],      -- country_code_id
+                $4::int4[],      -- metric_name_id
+                $5::int4[],      -- metric_value
+                $6::int4[],      -- platform_id
+                $7::int4[],      -- version_id
+                $8::int2[],      -- woi
+                $9::int2[],      -- wos
+                $10::int2[],     -- yoi
+                $11::int2[],     -- yos
+                $12::text[],     -- idempotency_key
+                $13::jsonb[]     -- extra
+            ) AS t (
+                cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+                platform_id, version_id, woi, wos, yoi, yos, idempotency_key, extra
+            )
+        ),
+        claimed AS (
+            INSERT INTO telemetry_event_keys (idempotency_key)
+            SELECT DISTINCT idempotency_key FROM input WHERE idempotency_key IS NOT NULL
+            ON CONFLICT (idempotency_key) DO NOTHING
+            RETURNING idempotency_key
         )
-        SELECT *
-        FROM UNNEST(
-            $1::text[],      -- cadence
-            $2::text[],      -- channel
-            $3::text[],      -- country_code
-            $4::text[],      -- metric_name
-            $5::float8[],    -- metric_value
-            $6::text[],      -- platform
-            $7::text[],      -- version
-            $8::int4[],      -- woi
-            $9::int4[],      -- wos
-            $10::int4[],     -- yoi
-            $11::int4[]     -- yos
+        INSERT INTO telemetry_event_facts (
+            cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+            platform_id, version_id, woi, wos, yoi, yos, extra
         )
+        SELECT cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+               platform_id, version_id, woi, wos, yoi, yos, extra
+        FROM input
+        WHERE idempotency_key IS NULL
+        UNION ALL
+        SELECT DISTINCT ON (idempotency_key)
+               cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+               platform_id, version_id, woi, wos, yoi, yos, extra
+        FROM input
+        WHERE idempotency_key IN (SELECT idempotency_key FROM claimed)
         "#
     )
         .bind(cadence)
@@ -57,9 +179,127 @@ pub async fn insert_events(
         .bind(wos)
         .bind(yoi)
         .bind(yos)
-        .execute(&mut *transaction)
+        .bind(idempotency_key)
+        .bind(extra)
+        .execute(&mut *conn)
         .await?;
 
+    let dropped = events.len() as u64 - result.rows_affected();
+    if dropped > 0 {
+        log::debug!("Dropped {} duplicate events", dropped);
+    }
+    Ok(resolved)
+}
+
+/// COPY cannot skip conflicting rows, so events carrying an idempotency key
+/// are written through UNNEST instead, in the same transaction: the batch is
+/// either written whole or not at all.
+pub async fn insert_events_copy(
+    pool: Arc<DBPool>,
+    events: Vec<MyPayload>,
+) -> Result<(), sqlx::Error> {
+    let (keyed, events): (Vec<_>, Vec<_>) = events.into_iter().partition(|e| e.idempotency_key.is_some());
+    if keyed.is_empty() && events.is_empty() {
+        return Ok(());
+    }
+
+    let mut transaction = pool.inner_pool.begin().await?;
+    let keyed = if keyed.is_empty() {
+        None
+    } else {
+        Some(write_unnest(&pool, &mut transaction, &keyed).await?)
+    };
+    let unkeyed = if events.is_empty() {
+        None
+    } else {
+        Some(write_copy(&pool, &mut transaction, &events).await?)
+    };
     transaction.commit().await?;
+    for resolved in keyed.into_iter().chain(unkeyed) {
+        pool.dimensions.remember(resolved);
+    }
     Ok(())
 }
+
+/// Inserts `events` through a binary COPY on `conn`, which the caller commits.
+async fn write_copy(pool: &DBPool, conn: &mut PgConnection, events: &[MyPayload]) -> Result<Resolved, sqlx::Error> {
+    let resolved = pool.dimensions.resolve(&mut *conn, events).await?;
+    let data = encode_copy_binary(events, &resolved.ids);
+    let mut copy = conn
+        .copy_in_raw(
+            r#"
+            COPY telemetry_event_facts (
+                cadence_id, channel_id, country_code_id, metric_name_id, metric_value,
+                platform_id, version_id, woi, wos, yoi, yos, extra
+            )
+            FROM STDIN (FORMAT binary)
+            "#,
+        )
+        .await?;
+    if let Err(e) = copy.send(data).await {
+        copy.abort(e.to_string()).await?;
+        return Err(e);
+    }
+    copy.finish().await?;
+    Ok(resolved)
+}
+
+/// The `extra` column value, NULL when the payload has no extra fields.
+fn extra_json(event: &MyPayload) -> Option<String> {
+    (!event.extra.is_empty()).then(|| serde_json::Value::Object(event.extra.clone()).to_string())
+}
+
+fn put_int2(buf: &mut Vec<u8>, value: i16) {
+    buf.extend_from_slice(&2i32.to_be_bytes());
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_null_or<T>(buf: &mut Vec<u8>, value: Option<T>, put: fn(&mut Vec<u8>, T)) {
+    match value {
+        Some(value) => put(buf, value),
+        None => buf.extend_from_slice(&COPY_NULL.to_be_bytes()),
+    }
+}
+
+fn put_jsonb(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            buf.extend_from_slice(&(value.len() as i32 + 1).to_be_bytes());
+            buf.push(JSONB_BINARY_VERSION);
+            buf.extend_from_slice(value.as_bytes());
+        }
+        None => buf.extend_from_slice(&COPY_NULL.to_be_bytes()),
+    }
+}
+
+fn put_int4(buf: &mut Vec<u8>, value: i32) {
+    buf.extend_from_slice(&4i32.to_be_bytes());
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Encodes events as a binary COPY stream into `telemetry_event_facts`, with
+/// `ids` holding the dimension ids of each event. Field types must match the
+/// table columns exactly: `INTEGER` for the ids and metric_value, `SMALLINT`
+/// for the weeks and years and `JSONB` for extra. A missing woi, wos or yoi
+/// is written as NULL.
+pub fn encode_copy_binary(events: &[MyPayload], ids: &[DimensionIds]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(COPY_BINARY_HEADER.len() + events.len() * 96);
+    buf.extend_from_slice(COPY_BINARY_HEADER);
+    for (e, ids) in events.iter().zip(ids) {
+        buf.extend_from_slice(&COPY_COLUMN_COUNT.to_be_bytes());
+        put_int4(&mut buf, ids.cadence);
+        put_int4(&mut buf, ids.channel);
+        put_int4(&mut buf, ids.country_code);
+        put_int4(&mut buf, ids.metric_name);
+        put_int4(&mut buf, e.metric_value);
+        put_int4(&mut buf, ids.platform);
+        put_int4(&mut buf, ids.version);
+        put_null_or(&mut buf, e.woi, put_int2);
+        put_null_or(&mut buf, e.wos, put_int2);
+        put_null_or(&mut buf, e.yoi, put_int2);
+        put_int2(&mut buf, e.yos);
+        put_jsonb(&mut buf, extra_json(e).as_deref());
+    }
+    buf.extend_from_slice(&COPY_BINARY_TRAILER.to_be_bytes());
+    buf
+}
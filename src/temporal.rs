@@ -0,0 +1,150 @@
+//! Sanity checks of the install (`woi`/`yoi`) and survey (`wos`/`yos`)
+//! weeks against each other and the server clock.
+//!
+//! Weeks are ISO weeks. Client clocks and time zones run ahead of the server,
+//! so a week only counts as future once it starts after today plus
+//! `TEMPORAL_FUTURE_TOLERANCE_DAYS`. Surveys older than
+//! `TEMPORAL_MAX_SURVEY_AGE_WEEKS` are rejected as stale.
+
+use chrono::{Datelike, Days, IsoWeek, NaiveDate, Weekday};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::payload::MyPayload;
+
+const FUTURE_TOLERANCE_DAYS_ENV_KEY: &str = "TEMPORAL_FUTURE_TOLERANCE_DAYS";
+const FUTURE_TOLERANCE_DAYS_DEFAULT: &str = "1";
+const MAX_SURVEY_AGE_WEEKS_ENV_KEY: &str = "TEMPORAL_MAX_SURVEY_AGE_WEEKS";
+const MAX_SURVEY_AGE_WEEKS_DEFAULT: &str = "12";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemporalViolation {
+    /// The week does not exist in the ISO calendar of its year.
+    InvalidWeek { field: &'static str, year: i16, week: i16 },
+    /// Only one of `woi` and `yoi` was given.
+    IncompleteInstall,
+    FutureSurvey,
+    StaleSurvey { max_age_weeks: u32 },
+    FutureInstall,
+    InstallAfterSurvey,
+}
+
+impl TemporalViolation {
+    /// Short label used in metrics and quarantine reasons.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TemporalViolation::InvalidWeek { .. } => "invalid_week",
+            TemporalViolation::IncompleteInstall => "incomplete_install",
+            TemporalViolation::FutureSurvey => "future_survey",
+            TemporalViolation::StaleSurvey { .. } => "stale_survey",
+            TemporalViolation::FutureInstall => "future_install",
+            TemporalViolation::InstallAfterSurvey => "install_after_survey",
+        }
+    }
+}
+
+impl fmt::Display for TemporalViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemporalViolation::InvalidWeek { field, year, week } => {
+                write!(f, "{} {} is not an ISO week of {}", field, week, year)
+            }
+            TemporalViolation::IncompleteInstall => write!(f, "woi and yoi must be given together"),
+            TemporalViolation::FutureSurvey => write!(f, "survey week is in the future"),
+            TemporalViolation::StaleSurvey { max_age_weeks } => {
+                write!(f, "survey week is more than {} weeks old", max_age_weeks)
+            }
+            TemporalViolation::FutureInstall => write!(f, "install week is in the future"),
+            TemporalViolation::InstallAfterSurvey => write!(f, "install week is after the survey week"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TemporalPolicy {
+    pub future_tolerance_days: u32,
+    pub max_survey_age_weeks: u32,
+}
+
+impl Default for TemporalPolicy {
+    fn default() -> Self {
+        Self {
+            future_tolerance_days: FUTURE_TOLERANCE_DAYS_DEFAULT.parse().unwrap(),
+            max_survey_age_weeks: MAX_SURVEY_AGE_WEEKS_DEFAULT.parse().unwrap(),
+        }
+    }
+}
+
+/// Monday of an ISO week, or `None` when the week doesn't exist.
+fn week_start(year: i16, week: i16) -> Option<NaiveDate> {
+    NaiveDate::from_isoywd_opt(year as i32, u32::try_from(week).ok()?, Weekday::Mon)
+}
+
+fn monday(week: IsoWeek) -> NaiveDate {
+    NaiveDate::from_isoywd_opt(week.year(), week.week(), Weekday::Mon).unwrap()
+}
+
+impl TemporalPolicy {
+    pub fn from_env() -> &'static Self {
+        static POLICY: OnceLock<TemporalPolicy> = OnceLock::new();
+        POLICY.get_or_init(|| {
+            let read = |key: &str, default: &str| {
+                u32::from_str(&env::var(key).unwrap_or(default.to_string()))
+                    .unwrap_or_else(|_| panic!("{} must be a non-negative integer", key))
+            };
+            TemporalPolicy {
+                future_tolerance_days: read(FUTURE_TOLERANCE_DAYS_ENV_KEY, FUTURE_TOLERANCE_DAYS_DEFAULT),
+                max_survey_age_weeks: read(MAX_SURVEY_AGE_WEEKS_ENV_KEY, MAX_SURVEY_AGE_WEEKS_DEFAULT),
+            }
+        })
+    }
+
+    /// Checks the weeks of `payload` as of `today`. Weeks a payload doesn't
+    /// carry are not checked.
+    pub fn check(&self, payload: &MyPayload, today: NaiveDate) -> Result<(), TemporalViolation> {
+        let install = match (payload.woi, payload.yoi) {
+            (Some(woi), Some(yoi)) => Some(week_start(yoi, woi).ok_or(TemporalViolation::InvalidWeek {
+                field: "woi",
+                year: yoi,
+                week: woi,
+            })?),
+            (None, None) => None,
+            _ => return Err(TemporalViolation::IncompleteInstall),
+        };
+        let survey = match payload.wos {
+            Some(wos) => Some(week_start(payload.yos, wos).ok_or(TemporalViolation::InvalidWeek {
+                field: "wos",
+                year: payload.yos,
+                week: wos,
+            })?),
+            None => None,
+        };
+        let horizon = today
+            .checked_add_days(Days::new(self.future_tolerance_days.into()))
+            .unwrap_or(today);
+        let latest = monday(horizon.iso_week());
+        if let Some(survey) = survey {
+            if survey > latest {
+                return Err(TemporalViolation::FutureSurvey);
+            }
+            let age_weeks = (monday(today.iso_week()) - survey).num_weeks();
+            if age_weeks > self.max_survey_age_weeks.into() {
+                return Err(TemporalViolation::StaleSurvey {
+                    max_age_weeks: self.max_survey_age_weeks,
+                });
+            }
+        }
+        let Some(install) = install else {
+            return Ok(());
+        };
+        if install > latest {
+            return Err(TemporalViolation::FutureInstall);
+        }
+        if survey.is_some_and(|survey| install > survey) {
+            return Err(TemporalViolation::InstallAfterSurvey);
+        }
+        Ok(())
+    }
+}
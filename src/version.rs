@@ -0,0 +1,80 @@
+//! Parsing of Brave-style dotted versions such as `1.80.113`.
+//!
+//! Clients report versions as free-form text. The leading numeric
+//! components are kept as major, minor, build and patch; anything after them
+//! (`1.80.113 Beta`, `v1.80.113-nightly`) is ignored. A version without a
+//! leading number can't be parsed and is stored without components.
+//! Components are at most nine digits, the same rule the backfill in
+//! `migrations/20250709090000_add_version_components` applies.
+
+use std::fmt;
+use std::str::FromStr;
+
+const MAX_COMPONENTS: usize = 4;
+/// Longer components end the version, so every component fits an `i32`.
+const MAX_COMPONENT_DIGITS: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: i32,
+    pub minor: Option<i32>,
+    pub build: Option<i32>,
+    pub patch: Option<i32>,
+}
+
+impl Version {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let value = value.strip_prefix(['v', 'V']).unwrap_or(value);
+        let mut components = Vec::with_capacity(MAX_COMPONENTS);
+        for part in value.split('.').take(MAX_COMPONENTS) {
+            let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+            if digits > MAX_COMPONENT_DIGITS {
+                break;
+            }
+            let Ok(number) = part[..digits].parse::<i32>() else {
+                break;
+            };
+            components.push(number);
+            // A suffix ends the version: `113-beta.2` is build 113.
+            if digits < part.len() {
+                break;
+            }
+        }
+        let major = *components.first()?;
+        Some(Version {
+            major,
+            minor: components.get(1).copied(),
+            build: components.get(2).copied(),
+            patch: components.get(3).copied(),
+        })
+    }
+
+    /// Components with missing ones as zero, for range comparisons.
+    pub fn padded(&self) -> [i32; MAX_COMPONENTS] {
+        [
+            self.major,
+            self.minor.unwrap_or(0),
+            self.build.unwrap_or(0),
+            self.patch.unwrap_or(0),
+        ]
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Version::parse(s).ok_or_else(|| format!("invalid version: {}", s))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.major)?;
+        for component in [self.minor, self.build, self.patch].into_iter().flatten() {
+            write!(f, ".{}", component)?;
+        }
+        Ok(())
+    }
+}
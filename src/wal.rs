@@ -0,0 +1,205 @@
+//! Append-only write-ahead log for accepted events.
+//!
+//! Every event acknowledged by `queue_job` is first appended to the current
+//! segment file as one NDJSON line. When the worker flushes a batch the segment
+//! is sealed and a new one is started; the sealed segment is removed once its
+//! batch is written. Segments left on disk (crash, failed write) are replayed
+//! into the event sink at startup.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, mem};
+
+use crate::error::AppError;
+use crate::payload::MyPayload;
+use crate::sink::EventSink;
+
+const WAL_DIR_ENV_KEY: &str = "WAL_DIR";
+const WAL_FSYNC_ENV_KEY: &str = "WAL_FSYNC";
+const WAL_FSYNC_DEFAULT: &str = "always";
+const SEGMENT_PREFIX: &str = "wal-";
+const SEGMENT_SUFFIX: &str = ".ndjson";
+
+/// When appended events are forced to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// fsync after every event, before it is acknowledged.
+    Always,
+    /// fsync once per segment, when the batch is handed to the database.
+    Batch,
+    /// Leave flushing to the OS. Survives process crashes, not power loss.
+    Never,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(FsyncPolicy::Always),
+            "batch" => Ok(FsyncPolicy::Batch),
+            "never" => Ok(FsyncPolicy::Never),
+            other => Err(format!("unknown fsync policy: {}", other)),
+        }
+    }
+}
+
+impl FsyncPolicy {
+    pub fn from_env() -> Self {
+        FsyncPolicy::from_str(
+            &env::var(WAL_FSYNC_ENV_KEY).unwrap_or(WAL_FSYNC_DEFAULT.to_string()),
+        )
+        .unwrap_or_else(|e| panic!("{} is invalid: {}", WAL_FSYNC_ENV_KEY, e))
+    }
+}
+
+/// Returns the WAL directory, or `None` when the WAL is disabled.
+pub fn wal_dir_from_env() -> Option<PathBuf> {
+    env::var(WAL_DIR_ENV_KEY)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+pub struct Wal {
+    dir: PathBuf,
+    fsync: FsyncPolicy,
+    seq: u64,
+    file: File,
+}
+
+impl Wal {
+    /// Opens a fresh segment numbered after any segment already in `dir`,
+    /// so pending segments are never appended to.
+    pub fn open(dir: impl Into<PathBuf>, fsync: FsyncPolicy) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let seq = pending_segments(&dir)?
+            .last()
+            .and_then(|path| segment_seq(path))
+            .map_or(0, |seq| seq + 1);
+        let file = open_segment(&dir, seq)?;
+        Ok(Self { dir, fsync, seq, file })
+    }
+
+    pub fn append(&mut self, event: &MyPayload) -> io::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        if self.fsync == FsyncPolicy::Always {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Seals the current segment and starts the next one. The returned path
+    /// should be passed to [`remove_segment`] once its events are committed.
+    pub fn rotate(&mut self) -> io::Result<PathBuf> {
+        let next = open_segment(&self.dir, self.seq + 1)?;
+        let sealed = mem::replace(&mut self.file, next);
+        if self.fsync == FsyncPolicy::Batch {
+            sealed.sync_data()?;
+        }
+        let sealed_path = segment_path(&self.dir, self.seq);
+        self.seq += 1;
+        Ok(sealed_path)
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{}{:020}{}", SEGMENT_PREFIX, seq, SEGMENT_SUFFIX))
+}
+
+fn segment_seq(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(SEGMENT_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+fn open_segment(dir: &Path, seq: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, seq))
+}
+
+/// Lists segment files in `dir`, oldest first.
+pub fn pending_segments(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut segments = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| segment_seq(path).is_some())
+        .collect::<Vec<_>>();
+    segments.sort_by_key(|path| segment_seq(path));
+    Ok(segments)
+}
+
+/// Reads every event in a segment. A torn final line, left behind by a crash
+/// in the middle of a write, is skipped.
+pub fn read_segment(path: &Path) -> io::Result<Vec<MyPayload>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(event) => events.push(event),
+            Err(e) => log::warn!("Skipping unreadable WAL entry in {}: {}", path.display(), e),
+        }
+    }
+    Ok(events)
+}
+
+/// Writes `events` to a new segment in `dir` and syncs it once. A segment
+/// that can't be written completely is removed again.
+pub fn write_segment(dir: &Path, events: &[MyPayload]) -> io::Result<PathBuf> {
+    let mut wal = Wal::open(dir, FsyncPolicy::Never)?;
+    let path = segment_path(dir, wal.seq);
+    let written = events
+        .iter()
+        .try_for_each(|event| wal.append(event))
+        .and_then(|()| wal.file.sync_data());
+    match written {
+        Ok(()) => Ok(path),
+        Err(e) => {
+            if let Err(remove) = remove_segment(&path) {
+                log::error!("Failed to remove partial segment {}: {}", path.display(), remove);
+            }
+            Err(e)
+        }
+    }
+}
+
+pub fn remove_segment(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the events of every pending segment to `sink` and removes each
+/// segment once it is written. Returns the number of replayed events.
+pub async fn replay(dir: &Path, sink: &dyn EventSink) -> Result<usize, AppError> {
+    let mut replayed = 0;
+    for segment in pending_segments(dir)? {
+        // A segment never holds more than one batch, so it is inserted in a
+        // single transaction and is either fully replayed or left in place.
+        let events = read_segment(&segment)?;
+        let count = events.len();
+        sink.write(&events).await?;
+        remove_segment(&segment)?;
+        replayed += count;
+    }
+    Ok(replayed)
+}
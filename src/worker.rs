@@ -1,41 +1,70 @@
 use actix::prelude::*;
+use std::io;
 use std::sync::Arc;
-use crate::models::DBPool;
+use crate::archive::{ArchiveMessage, Archiver};
+use crate::metrics;
 use crate::payload::MyPayload;
-use crate::telemetry_event::insert_events;
+use crate::sink::EventSink;
+use crate::wal::{remove_segment, Wal};
+
+/// Number of buffered events written to the sink in one batch.
+pub const BATCH_SIZE: usize = 100;
 
-#[derive(Clone)]
 pub struct ActorWorker {
-    pub pool: Arc<DBPool>,
+    pub sink: Arc<dyn EventSink>,
     pub buffer: Vec<MyPayload>,
+    pub wal: Option<Wal>,
+    pub archive: Option<Addr<Archiver>>,
 }
 
 pub struct DeliveryMessage(pub MyPayload);
 
 impl actix::Message for DeliveryMessage {
-    type Result = ();
+    type Result = io::Result<()>;
 }
 
 impl Handler<DeliveryMessage> for ActorWorker {
-    type Result = ();
+    type Result = io::Result<()>;
 
     fn handle(&mut self, msg: DeliveryMessage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.append(&msg.0)?;
+        }
         self.buffer.push(msg.0);
 
-        if self.buffer.len() >= 100 {
-            let pool = self.pool.clone();
-            let buffer = self.buffer.clone();
+        if self.buffer.len() >= BATCH_SIZE {
+            let segment = match self.wal.as_mut() {
+                Some(wal) => Some(wal.rotate()?),
+                None => None,
+            };
+            let sink = self.sink.clone();
+            let buffer = std::mem::take(&mut self.buffer);
+            if let Some(archive) = &self.archive {
+                archive.do_send(ArchiveMessage(buffer.clone()));
+            }
             actix::spawn(async move {
-                if let Err(e) = insert_events(pool.clone(), buffer).await {
-                    eprintln!("Failed to insert events: {:?}", e);
+                match sink.write(&buffer).await {
+                    Ok(()) => {
+                        metrics::increment("insert_batches_total", &[("result", "ok")]);
+                        if let Some(segment) = segment
+                            && let Err(e) = remove_segment(&segment)
+                        {
+                            log::error!("Failed to remove WAL segment {}: {}", segment.display(), e);
+                        }
+                    }
+                    // The sealed segment stays on disk and is replayed at next startup.
+                    Err(e) => {
+                        metrics::increment("insert_batches_total", &[("result", "error")]);
+                        log::error!("Failed to write events to {}: {:?}", sink.name(), e)
+                    }
                 }
             });
-            self.buffer.clear();
         }
+        Ok(())
     }
 }
 
 impl Actor for ActorWorker {
     type Context = Context<Self>;
-    
-}
\ No newline at end of file
+
+}
@@ -0,0 +1,126 @@
+// tests/access_log_tests.rs
+//
+// Captures everything logged during a submission and checks that the client
+// address never appears, neither in logs nor in what is stored.
+
+mod common;
+
+use actix_web::{test, web, App};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use telemetry_events::access_log::{truncate_ip, truncate_user_agent, AccessLog, LogFormat, Redaction};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::queue_job::{queue_job, EventQueue};
+use telemetry_events::wal;
+use telemetry_events::sink::PostgresSink;
+
+use common::{connect_test_db, recent_payload, start_worker, test_db_configured, unused_pool};
+
+const CLIENT_IP: &str = "203.0.113.77";
+const FORWARDED_IP: &str = "198.51.100.23";
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) Brave/1.79";
+
+struct CaptureLogger;
+
+fn captured() -> &'static Mutex<Vec<String>> {
+    static LINES: OnceLock<Mutex<Vec<String>>> = OnceLock::new();
+    LINES.get_or_init(Default::default)
+}
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        captured().lock().unwrap().push(format!("{} {}", record.target(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+fn install_logger() {
+    static INSTALLED: OnceLock<()> = OnceLock::new();
+    INSTALLED.get_or_init(|| {
+        log::set_boxed_logger(Box::new(CaptureLogger)).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
+fn test_payload() -> MyPayload {
+    MyPayload { metric_name: "Test.AccessLog".to_string(), ..recent_payload() }
+}
+
+#[actix_web::test]
+async fn truncation_keeps_only_the_network_and_product() {
+    assert_eq!(truncate_ip(CLIENT_IP.parse().unwrap()), "203.0.113.0".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        truncate_ip("2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap()),
+        "2001:db8:85a3::".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(truncate_user_agent(USER_AGENT), "Mozilla/5.0");
+}
+
+#[actix_web::test]
+async fn client_ip_never_reaches_logs_or_storage() {
+    install_logger();
+    let db_configured = test_db_configured();
+    let pool = if db_configured { connect_test_db(2).await } else { unused_pool() };
+    let wal_dir = tempfile::tempdir().unwrap();
+    let worker_addr = start_worker(pool.clone(), Some(wal_dir.path()));
+    let format = LogFormat::new("%a \"%r\" %s %b %{User-Agent}i %{X-Forwarded-For}i", Redaction::Truncate, Redaction::Truncate);
+    let app = test::init_service(
+        App::new()
+            .wrap(AccessLog::new(format))
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/p3a")
+        .peer_addr(format!("{}:50000", CLIENT_IP).parse().unwrap())
+        .insert_header(("X-Forwarded-For", FORWARDED_IP))
+        .insert_header(("User-Agent", USER_AGENT))
+        .set_json(test_payload())
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let lines = captured().lock().unwrap().clone();
+    let access_line = lines
+        .iter()
+        .find(|line| line.starts_with("access_log "))
+        .expect("no access log line");
+    // The peer isn't a trusted proxy, so its forwarded address is ignored.
+    assert!(access_line.contains("203.0.113.0 \"POST /api/v1/p3a HTTP/1.1\" 200"), "{}", access_line);
+    assert!(access_line.contains(" Mozilla/5.0 %{X-Forwarded-For}i"), "{}", access_line);
+    for line in &lines {
+        assert!(!line.contains(CLIENT_IP) && !line.contains(FORWARDED_IP), "{}", line);
+        assert!(!line.contains("Brave/1.79"), "{}", line);
+    }
+
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    let stored = std::fs::read_to_string(&segments[0]).unwrap();
+    assert!(!stored.contains(CLIENT_IP) && !stored.contains(FORWARDED_IP));
+
+    if db_configured {
+        wal::replay(wal_dir.path(), &PostgresSink::new(Arc::new(DBPool::from(pool.clone()))))
+            .await
+            .unwrap();
+        let leaked: i64 = sqlx::query_scalar(
+            "SELECT count(*) FROM telemetry_events t WHERE t::text LIKE $1 OR t::text LIKE $2",
+        )
+        .bind(format!("%{}%", CLIENT_IP))
+        .bind(format!("%{}%", FORWARDED_IP))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(leaked, 0);
+        sqlx::query("DELETE FROM telemetry_events WHERE metric_name = 'Test.AccessLog'")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+// tests/adoption_tests.rs
+
+mod common;
+
+use actix_web::{test, web, App};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use telemetry_events::adoption::{
+    build_report, count_versions, get_adoption, to_csv, AdoptionQuery, Granularity, ReportFormat, VersionCount,
+    OTHER_VERSION,
+};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::telemetry_event::insert_events_unnest;
+
+use common::{connect_test_db, connect_test_db_in_time_zone, test_db_configured, test_payload};
+
+fn count(day: u32, platform: &str, version: &str, reports: i64) -> VersionCount {
+    VersionCount {
+        period: NaiveDate::from_ymd_opt(2025, 6, day).unwrap(),
+        platform: platform.to_string(),
+        channel: "release".to_string(),
+        version: version.to_string(),
+        reports,
+    }
+}
+
+#[actix_web::test]
+async fn report_keeps_top_versions_and_buckets_the_rest() {
+    let rows = build_report(
+        vec![
+            count(2, "linux", "1.79.1", 60),
+            count(2, "linux", "1.80.1", 30),
+            count(2, "linux", "1.78.9", 6),
+            count(2, "linux", "1.77.0", 4),
+            count(9, "linux", "1.80.1", 70),
+            count(9, "linux", "1.79.1", 25),
+            count(9, "linux", "1.81.0", 5),
+            count(2, "macos", "1.78.9", 10),
+        ],
+        2,
+    );
+    let summary: Vec<(u32, &str, &str, i64)> = rows
+        .iter()
+        .map(|r| (r.period.day(), r.platform.as_str(), r.version.as_str(), r.reports))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (2, "linux", "1.79.1", 60),
+            (2, "linux", "1.80.1", 30),
+            (2, "linux", OTHER_VERSION, 10),
+            (2, "macos", "1.78.9", 10),
+            (9, "linux", "1.80.1", 70),
+            (9, "linux", "1.79.1", 25),
+            (9, "linux", OTHER_VERSION, 5),
+        ]
+    );
+    assert_eq!(rows[0].share, 0.6);
+    assert_eq!(rows[3].share, 1.0);
+
+    let csv = to_csv(&build_report(vec![count(2, "linux", "1.80,\"beta\"", 1)], 5));
+    assert_eq!(csv, "period,platform,channel,version,reports,share\n2025-06-02,linux,release,\"1.80,\"\"beta\"\"\",1,1.000000\n");
+}
+
+#[actix_web::test]
+async fn adoption_endpoint_serves_json_and_csv() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = format!("Test.Adoption.{:08x}", rand::random::<u32>());
+    let event = |version: &str| MyPayload {
+        channel: "nightly".to_string(),
+        metric_name: metric_name.clone(),
+        version: version.to_string(),
+        ..test_payload()
+    };
+    let versions = ["1.81.1", "1.81.1", "1.81.1", "1.80.2", "1.79.5"];
+    insert_events_unnest(pool.clone(), versions.iter().map(|v| event(v)).collect()).await.unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(pool))
+            .route("/api/v1/reports/adoption", web::get().to(get_adoption)),
+    )
+    .await;
+    let today = Utc::now().date_naive();
+    let uri = |extra: &str| {
+        format!(
+            "/api/v1/reports/adoption?from={}&to={}&granularity=day&top=1&metric_name={}{}",
+            today, today, metric_name, extra
+        )
+    };
+    let req = test::TestRequest::get().uri(&uri("")).to_request();
+    let rows: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        rows,
+        json!([
+            {"period": today, "platform": "linux", "channel": "nightly", "version": "1.81.1", "reports": 3, "share": 0.6},
+            {"period": today, "platform": "linux", "channel": "nightly", "version": "other", "reports": 2, "share": 0.4}
+        ])
+    );
+
+    let req = test::TestRequest::get().uri(&uri("&format=csv")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/csv; charset=utf-8");
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        format!(
+            "period,platform,channel,version,reports,share\n{today},linux,nightly,1.81.1,3,0.600000\n{today},linux,nightly,other,2,0.400000\n"
+        )
+    );
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(&metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn adoption_days_are_utc_days_in_any_session_time_zone() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db_in_time_zone(2, "Asia/Bangkok").await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = format!("Test.AdoptionZone.{:08x}", rand::random::<u32>());
+    let event = |version: &str| MyPayload {
+        metric_name: metric_name.clone(),
+        version: version.to_string(),
+        ..test_payload()
+    };
+    insert_events_unnest(pool.clone(), vec![event("1.80.1"), event("1.80.2")]).await.unwrap();
+    // Late on the UTC day before, and late on the UTC day itself.
+    for (version, received_at) in [("1.80.1", "2025-04-30T20:00:00Z"), ("1.80.2", "2025-05-01T20:00:00Z")] {
+        sqlx::query(
+            "UPDATE telemetry_event_facts SET received_at = $3::timestamptz \
+             WHERE metric_name_id = (SELECT id FROM telemetry_dim_metric_name WHERE value = $1) \
+               AND version_id = (SELECT id FROM telemetry_dim_version WHERE value = $2)",
+        )
+        .bind(&metric_name)
+        .bind(version)
+        .bind(received_at)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+    }
+
+    let may_day = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+    let query = AdoptionQuery {
+        from: may_day,
+        to: may_day,
+        granularity: Granularity::Day,
+        top: None,
+        metric_name: Some(metric_name.clone()),
+        platform: None,
+        channel: None,
+        format: ReportFormat::Json,
+    };
+    let counts = count_versions(&pool, &query).await.unwrap();
+    let versions: Vec<(NaiveDate, &str)> = counts.iter().map(|c| (c.period, c.version.as_str())).collect();
+    assert_eq!(versions, vec![(may_day, "1.80.2")]);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(&metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
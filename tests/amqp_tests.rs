@@ -0,0 +1,60 @@
+// tests/amqp_tests.rs
+//
+// Runs against a local broker, e.g. `docker compose up rabbitmq`, with
+// TEST_RABBITMQ_URL and TEST_DATABASE_URL set. Skipped otherwise.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use telemetry_events::amqp::{run_consumer, AmqpConfig, AmqpPublisher};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::sink::PostgresSink;
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+fn test_config() -> Option<AmqpConfig> {
+    if !test_db_configured() {
+        return None;
+    }
+    Some(AmqpConfig {
+        url: std::env::var("TEST_RABBITMQ_URL").ok()?,
+        exchange: "telemetry_events_test".to_string(),
+        queue: "telemetry_events_test".to_string(),
+    })
+}
+
+#[actix_web::test]
+async fn published_events_are_consumed_into_database() {
+    let Some(config) = test_config() else {
+        return;
+    };
+    let pool = connect_test_db(2).await;
+    let metric_name = format!("Test.Amqp.{}", rand::random::<u32>());
+
+    let publisher = AmqpPublisher::connect(&config).await.unwrap();
+    for value in 0..3 {
+        let payload = MyPayload { metric_name: metric_name.clone(), metric_value: value, ..test_payload() };
+        publisher.publish(&payload).await.unwrap();
+    }
+
+    let sink = Arc::new(PostgresSink::new(Arc::new(DBPool::from(pool.clone()))));
+    let consumer = tokio::spawn(async move { run_consumer(&config, sink, None).await });
+
+    let mut count = 0;
+    for _ in 0..30 {
+        count = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM telemetry_events WHERE metric_name = $1")
+            .bind(&metric_name)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if count == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    consumer.abort();
+    assert_eq!(count, 3);
+}
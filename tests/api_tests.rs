@@ -1,31 +1,96 @@
 // tests/api_tests.rs
 
-use actix::Actor;
-use actix_web::{test, web, App};
-
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::sync::Arc;
-
-use telemetry_events::{
-    payload::MyPayload,
-    worker::{ActorWorker},
-};
-use telemetry_events::queue_job::queue_job;
-
-// Helper function to create test database pool
-async fn setup_test_db() -> Pool<Postgres> {
-    // Use a test-specific database URL or environment variable
-    dotenvy::dotenv().ok();
-    let database_url = std::env::var("TEST_DATABASE_URL")
-        .unwrap_or_else(|_| std::env::var("DATABASE_URL").expect("DATABASE_URL not set"));
-
-    PgPoolOptions::new()
-        .max_connections(2)
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to test database")
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use std::time::Duration;
+
+use telemetry_events::dedup::DedupStore;
+use telemetry_events::queue_job::{queue_job, EventQueue, IDEMPOTENCY_KEY_HEADER, MAX_PAYLOAD_SIZE};
+use telemetry_events::routers::service_scope;
+use telemetry_events::wal;
+
+use common::{connect_test_db, recent_payload, start_worker, test_db_configured, unused_pool};
+
+#[actix_web::test]
+async fn queued_job_is_written_to_wal_before_ack() {
+    if !test_db_configured() {
+        return;
+    }
+    let wal_dir = tempfile::tempdir().unwrap();
+    let worker_addr = start_worker(connect_test_db(2).await, Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/p3a")
+        .set_json(recent_payload())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    let events = wal::read_segment(&segments[0]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].metric_name, "Test.Metric");
+}
+
+#[actix_web::test]
+async fn retried_job_with_idempotency_key_is_queued_once() {
+    let wal_dir = tempfile::tempdir().unwrap();
+    // Nothing reaches the database below BATCH_SIZE events, so the pool is never used.
+    let worker_addr = start_worker(unused_pool(), Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .app_data(web::Data::new(DedupStore::new(Duration::from_secs(60), 100)))
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+
+    for _ in 0..3 {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/p3a")
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "retry-1"))
+            .set_json(recent_payload())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    let events = wal::read_segment(&segments[0]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].idempotency_key.as_deref(), Some("retry-1"));
+}
+
+#[actix_web::test]
+async fn submissions_must_be_small_json() {
+    let wal_dir = tempfile::tempdir().unwrap();
+    let worker_addr = start_worker(unused_pool(), Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .service(service_scope()),
+    )
+    .await;
+
+    let body = serde_json::to_string(&recent_payload()).unwrap();
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_payload(body.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let mut oversized = recent_payload();
+    oversized.version = "1".repeat(MAX_PAYLOAD_SIZE);
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_json(oversized).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/p3a")
+        .insert_header(("Content-Type", "application/json; charset=utf-8"))
+        .set_payload(body)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
 }
- 
-// Setup function for the app context with test dependencies
- 
- 
\ No newline at end of file
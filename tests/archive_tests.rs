@@ -0,0 +1,77 @@
+// tests/archive_tests.rs
+//
+// Uses a temporary directory by default. Set TEST_ARCHIVE_URL (and
+// S3_ENDPOINT) to run against localstack or MinIO instead, e.g.
+// TEST_ARCHIVE_URL=s3://ibrowe-core-ext/test S3_ENDPOINT=http://localhost:4566
+
+mod common;
+
+use actix::Actor;
+use chrono::{NaiveDate, Utc};
+use std::time::Duration;
+
+use telemetry_events::archive::{list_archives, read_archive, write_batch, ArchiveMessage, Archiver, FlushArchive};
+use telemetry_events::payload::MyPayload;
+use telemetry_events::storage::store_from_url;
+
+fn test_payload(channel: &str, metric_value: i32) -> MyPayload {
+    MyPayload { channel: channel.to_string(), metric_value, wos: None, ..common::test_payload() }
+}
+
+#[actix_web::test]
+async fn archived_batches_read_back_by_date_and_channel() {
+    dotenvy::dotenv().ok();
+    let dir = tempfile::tempdir().unwrap();
+    let url = std::env::var("TEST_ARCHIVE_URL")
+        .map(|url| format!("{}/{}", url, rand::random::<u32>()))
+        .unwrap_or_else(|_| format!("file://{}", dir.path().display()));
+    let (store, prefix) = store_from_url(&url).unwrap();
+    let date = NaiveDate::from_ymd_opt(2025, 6, 18).unwrap();
+    let other_date = NaiveDate::from_ymd_opt(2025, 6, 19).unwrap();
+
+    let release = vec![test_payload("release", 1), test_payload("release", 2)];
+    write_batch(store.as_ref(), &prefix, date, "release", &release).await.unwrap();
+    write_batch(store.as_ref(), &prefix, date, "nightly", &[test_payload("nightly", 3)])
+        .await
+        .unwrap();
+    write_batch(store.as_ref(), &prefix, other_date, "release", &[test_payload("release", 4)])
+        .await
+        .unwrap();
+
+    let all = list_archives(store.as_ref(), &prefix, None, None).await.unwrap();
+    assert_eq!(all.len(), 3);
+
+    let for_date = list_archives(store.as_ref(), &prefix, Some(date), None).await.unwrap();
+    assert_eq!(for_date.len(), 2);
+
+    let release_any_date = list_archives(store.as_ref(), &prefix, None, Some("release"))
+        .await
+        .unwrap();
+    assert_eq!(release_any_date.len(), 2);
+
+    let locations = list_archives(store.as_ref(), &prefix, Some(date), Some("release"))
+        .await
+        .unwrap();
+    assert_eq!(locations.len(), 1);
+    let events = read_archive(store.as_ref(), &locations[0]).await.unwrap();
+    let values: Vec<i32> = events.iter().map(|e| e.metric_value).collect();
+    assert_eq!(values, vec![1, 2]);
+    assert_eq!(events[0].wos, None);
+}
+
+#[actix_web::test]
+async fn flush_waits_for_pending_uploads() {
+    let dir = tempfile::tempdir().unwrap();
+    let (store, prefix) = store_from_url(&format!("file://{}", dir.path().display())).unwrap();
+    let archiver = Archiver::new(store.clone(), prefix.clone(), Duration::from_secs(3600)).start();
+    archiver
+        .send(ArchiveMessage(vec![test_payload("release", 1), test_payload("nightly", 2)]))
+        .await
+        .unwrap();
+    archiver.send(FlushArchive).await.unwrap();
+
+    let locations = list_archives(store.as_ref(), &prefix, Some(Utc::now().date_naive()), None)
+        .await
+        .unwrap();
+    assert_eq!(locations.len(), 2);
+}
@@ -0,0 +1,48 @@
+// tests/client_ip_tests.rs
+
+use actix_web::test;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use telemetry_events::client_ip::{ip_digest, TrustedProxies};
+
+fn ip(addr: &str) -> Option<IpAddr> {
+    Some(addr.parse().unwrap())
+}
+
+#[actix_web::test]
+async fn forwarded_for_is_only_trusted_from_proxies() {
+    let proxies = TrustedProxies::from_str("10.0.0.1, 10.0.0.2").unwrap();
+    let request = |peer: &str, forwarded: Option<&str>| {
+        let mut req = test::TestRequest::default().peer_addr(format!("{}:443", peer).parse().unwrap());
+        if let Some(forwarded) = forwarded {
+            req = req.insert_header(("X-Forwarded-For", forwarded));
+        }
+        req.to_http_request()
+    };
+
+    assert_eq!(proxies.client_ip(&request("192.0.2.1", Some("198.51.100.1"))), ip("192.0.2.1"));
+    assert_eq!(proxies.client_ip(&request("10.0.0.1", None)), ip("10.0.0.1"));
+    assert_eq!(proxies.client_ip(&request("10.0.0.1", Some("198.51.100.1"))), ip("198.51.100.1"));
+    // Addresses left of the first untrusted hop are client supplied.
+    assert_eq!(
+        proxies.client_ip(&request("10.0.0.1", Some("203.0.113.9, 198.51.100.1, 10.0.0.2"))),
+        ip("198.51.100.1")
+    );
+    assert_eq!(proxies.client_ip(&request("10.0.0.1", Some("garbage, 10.0.0.2"))), ip("10.0.0.2"));
+    assert_eq!(proxies.client_ip(&test::TestRequest::default().to_http_request()), None);
+
+    assert!(TrustedProxies::from_str("").is_ok());
+    assert!(TrustedProxies::from_str("10.0.0.0/8").is_err());
+}
+
+#[actix_web::test]
+async fn ip_digest_is_keyed() {
+    let digest = ip_digest(ip("203.0.113.77"));
+    assert_eq!(digest.len(), 32);
+    assert_eq!(digest, ip_digest(ip("203.0.113.77")));
+    assert_ne!(digest, ip_digest(ip("203.0.113.78")));
+    // Not the plain SHA-256 of the address.
+    assert!(!format!("{:x}", Sha256::digest(b"203.0.113.77")).starts_with(&digest));
+}
@@ -1,47 +1,94 @@
-use crate::telemetry_event;
-use sqlx::PgPool;
-use std::sync::Once;
-use dotenvy::dotenv;
+// tests/common/mod.rs
+//
+// Helpers shared by the integration tests. Each test crate only uses some
+// of them.
+#![allow(dead_code)]
 
-static INIT: Once = Once::new();
+use actix::{Actor, Addr};
+use chrono::{Datelike, Utc};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+use std::path::Path;
+use std::sync::Arc;
 
-pub async fn setup_test_db() -> PgPool {
-    // Load .env.test if exists, fallback to .env
-    INIT.call_once(|| {
-        dotenvy::from_filename(".env.test").ok();
-        dotenv().ok();
-    });
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::sink::PostgresSink;
+use telemetry_events::wal::{FsyncPolicy, Wal};
+use telemetry_events::worker::ActorWorker;
 
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in .env.test or .env");
+// Database-backed tests only run when TEST_DATABASE_URL is configured
+pub fn test_db_configured() -> bool {
+    dotenvy::dotenv().ok();
+    std::env::var("TEST_DATABASE_URL").is_ok()
+}
 
-    let pool = PgPool::connect(&database_url)
+pub async fn connect_test_db(max_connections: u32) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(&std::env::var("TEST_DATABASE_URL").unwrap())
         .await
-        .expect("Failed to connect to database");
+        .expect("Failed to connect to test database")
+}
 
-    // Clear test database
-    sqlx::query!("TRUNCATE TABLE telemetry_events")
-        .execute(&pool)
+/// Like [`connect_test_db`] with sessions in `time_zone`. sqlx asks for UTC
+/// on connect, so the zone is changed afterwards.
+pub async fn connect_test_db_in_time_zone(max_connections: u32, time_zone: &'static str) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .after_connect(move |conn, _| {
+            Box::pin(async move {
+                conn.execute(format!("SET TimeZone = '{}'", time_zone).as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect(&std::env::var("TEST_DATABASE_URL").unwrap())
         .await
-        .expect("Failed to clear test database");
+        .expect("Failed to connect to test database")
+}
+
+/// A pool that never connects, for workers that are never sent a full batch.
+pub fn unused_pool() -> PgPool {
+    PgPoolOptions::new().connect_lazy("postgres://localhost/unused").unwrap()
+}
 
-    pool
+/// Starts a worker writing to `pool`, with a WAL in `wal_dir` if given.
+pub fn start_worker(pool: PgPool, wal_dir: Option<&Path>) -> Addr<ActorWorker> {
+    ActorWorker {
+        sink: Arc::new(PostgresSink::new(Arc::new(DBPool::from(pool)))),
+        buffer: Default::default(),
+        wal: wal_dir.map(|dir| Wal::open(dir, FsyncPolicy::Always).unwrap()),
+        archive: None,
+    }
+    .start()
 }
 
-pub fn get_test_event() -> TelemetryEvent {
-    TelemetryEvent {
-        id: None,
-        cadence: "daily".to_string(),
+/// An event of week 21 of 2025. Tests change the fields they care about.
+pub fn test_payload() -> MyPayload {
+    MyPayload {
+        cadence: "typical".to_string(),
         channel: "release".to_string(),
         country_code: "TH".to_string(),
-        metric_name: "test_metric".to_string(),
-        metric_value: 42,
-        platform: "desktop".to_string(),
-        version: "1.0.0".to_string(),
-        woi: 1,
-        wos: 2,
-        yoi: 2024,
-        yos: 12,
-        received_at: Some(chrono::Utc::now()),
+        metric_name: "Test.Metric".to_string(),
+        metric_value: 1,
+        platform: "linux".to_string(),
+        version: "1.0".to_string(),
+        woi: Some(21),
+        wos: Some(21),
+        yoi: Some(2025),
+        yos: 2025,
+        idempotency_key: None,
+        extra: Default::default(),
     }
-}
\ No newline at end of file
+}
+
+/// [`test_payload`] surveyed this week, since surveys must be recent to
+/// pass ingestion.
+pub fn recent_payload() -> MyPayload {
+    let survey = Utc::now().date_naive().iso_week();
+    MyPayload {
+        wos: Some(survey.week() as i16),
+        yos: survey.year() as i16,
+        ..test_payload()
+    }
+}
@@ -0,0 +1,105 @@
+// tests/dedup_tests.rs
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use telemetry_events::dedup::DedupStore;
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::telemetry_event::{insert_events_copy, insert_events_unnest};
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+const METRIC_NAME: &str = "Test.Dedup";
+
+fn keyed_payload(key: Option<&str>) -> MyPayload {
+    MyPayload {
+        metric_name: METRIC_NAME.to_string(),
+        idempotency_key: key.map(str::to_string),
+        ..test_payload()
+    }
+}
+
+#[test]
+fn dedup_store_forgets_keys_after_window_or_capacity() {
+    let store = DedupStore::new(Duration::from_millis(50), 2);
+    assert!(store.insert("a"));
+    assert!(!store.insert("a"));
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(store.insert("a"));
+
+    assert!(store.insert("b"));
+    assert!(store.insert("c"));
+    // "a" was the oldest of three keys in a store holding two.
+    assert!(store.insert("a"));
+    assert!(store.len() <= 3);
+
+    store.remove("c");
+    assert!(store.insert("c"));
+}
+
+#[test]
+fn dedup_store_removed_keys_do_not_count_against_capacity() {
+    let store = DedupStore::new(Duration::from_secs(60), 3);
+    assert!(store.insert("a"));
+    // A failed enqueue and its retry leave one entry for "a".
+    store.remove("a");
+    assert!(store.insert("a"));
+    assert!(store.insert("b"));
+    assert!(!store.insert("a"));
+    assert!(!store.insert("b"));
+}
+
+#[actix_web::test]
+async fn insert_drops_repeated_idempotency_keys() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let prefix = format!("dedup-{:08x}", rand::random::<u32>());
+    let key = |n: u32| Some(format!("{}-{}", prefix, n));
+
+    insert_events_unnest(
+        pool.clone(),
+        vec![
+            keyed_payload(key(1).as_deref()),
+            keyed_payload(key(1).as_deref()),
+            keyed_payload(key(2).as_deref()),
+            keyed_payload(None),
+        ],
+    )
+    .await
+    .unwrap();
+    // A retried batch, written through COPY, only adds its unkeyed and new rows.
+    insert_events_copy(
+        pool.clone(),
+        vec![
+            keyed_payload(key(1).as_deref()),
+            keyed_payload(key(3).as_deref()),
+            keyed_payload(None),
+        ],
+    )
+    .await
+    .unwrap();
+
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM telemetry_events WHERE metric_name = $1")
+        .bind(METRIC_NAME)
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 5);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(METRIC_NAME)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM telemetry_event_keys WHERE idempotency_key LIKE $1")
+        .bind(format!("{}-%", prefix))
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
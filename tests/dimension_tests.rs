@@ -0,0 +1,115 @@
+// tests/dimension_tests.rs
+
+mod common;
+
+use std::sync::Arc;
+
+use telemetry_events::dimensions::{Dimension, DimensionCache};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::telemetry_event::{insert_events_copy, insert_events_unnest};
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+fn payload(metric_name: &str, version: &str) -> MyPayload {
+    MyPayload { metric_name: metric_name.to_string(), version: version.to_string(), ..test_payload() }
+}
+
+#[actix_web::test]
+async fn dimension_ids_are_created_once_and_cached() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let metric_name = format!("Test.Dimension.{:08x}", rand::random::<u32>());
+    let events = vec![
+        payload(&metric_name, "1.80.1"),
+        payload(&metric_name, "1.80.2"),
+        payload(&metric_name, "1.80.1"),
+    ];
+
+    let count = |metric_name: String| {
+        let pg_pool = pg_pool.clone();
+        async move {
+            sqlx::query_scalar::<_, i64>("SELECT count(*) FROM telemetry_dim_metric_name WHERE value = $1")
+                .bind(metric_name)
+                .fetch_one(&pg_pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    // Values created by a batch that rolls back are neither stored nor cached.
+    let cache = DimensionCache::default();
+    let mut transaction = pg_pool.begin().await.unwrap();
+    cache.resolve(&mut transaction, &events).await.unwrap();
+    transaction.rollback().await.unwrap();
+    assert_eq!(count(metric_name.clone()).await, 0);
+    assert_eq!(cache.cached(Dimension::MetricName, &metric_name), None);
+
+    let mut transaction = pg_pool.begin().await.unwrap();
+    let resolved = cache.resolve(&mut transaction, &events).await.unwrap();
+    transaction.commit().await.unwrap();
+    let ids = resolved.ids.clone();
+    assert_eq!(ids[0], ids[2]);
+    assert_ne!(ids[0].version, ids[1].version);
+    assert_eq!(ids[0].metric_name, ids[1].metric_name);
+    assert_eq!(cache.cached(Dimension::MetricName, &metric_name), None);
+    cache.remember(resolved);
+    assert_eq!(cache.cached(Dimension::MetricName, &metric_name), Some(ids[0].metric_name));
+
+    // A second cache, like another writer process, gets the same ids.
+    let mut conn = pg_pool.acquire().await.unwrap();
+    let other = DimensionCache::default().resolve(&mut conn, &events).await.unwrap();
+    assert_eq!(other.ids, ids);
+    assert_eq!(count(metric_name).await, 1);
+}
+
+#[actix_web::test]
+async fn view_keeps_previous_column_shape() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = format!("Test.View.{:08x}", rand::random::<u32>());
+
+    insert_events_unnest(pool.clone(), vec![payload(&metric_name, "1.80.1")]).await.unwrap();
+    insert_events_copy(pool, vec![payload(&metric_name, "1.81.0")]).await.unwrap();
+
+    let columns: Vec<String> = sqlx::query_scalar(
+        "SELECT column_name::text FROM information_schema.columns WHERE table_name = 'telemetry_events' ORDER BY ordinal_position",
+    )
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        columns,
+        vec![
+            "id", "cadence", "channel", "country_code", "metric_name", "metric_value", "platform", "version",
+            "woi", "wos", "yoi", "yos", "received_at", "extra"
+        ]
+    );
+    let versions: Vec<String> =
+        sqlx::query_scalar("SELECT version FROM telemetry_events WHERE metric_name = $1 ORDER BY id")
+            .bind(&metric_name)
+            .fetch_all(&pg_pool)
+            .await
+            .unwrap();
+    assert_eq!(versions, vec!["1.80.1", "1.81.0"]);
+
+    let deleted = sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(&metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(deleted.rows_affected(), 2);
+    let remaining: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM telemetry_event_facts f JOIN telemetry_dim_metric_name m ON m.id = f.metric_name_id WHERE m.value = $1",
+    )
+    .bind(&metric_name)
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining, 0);
+}
@@ -0,0 +1,175 @@
+// tests/export_tests.rs
+
+mod common;
+
+use arrow_array::{Array, Int16Array, Int32Array, StringArray};
+use chrono::{NaiveDate, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::json;
+use std::fs::File;
+use std::sync::Arc;
+
+use telemetry_events::export::{self, ExportFormat, ExportOptions};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::telemetry_event::insert_events_unnest;
+
+use common::{connect_test_db, connect_test_db_in_time_zone, test_db_configured, test_payload};
+
+#[actix_web::test]
+async fn export_writes_matching_events_in_chunks() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let prefix = format!("Test.Export{:08x}", rand::random::<u32>());
+    let now = Utc::now();
+    let event = |metric: &str, value: i32, channel: &str, wos: Option<i16>| MyPayload {
+        channel: channel.to_string(),
+        metric_name: metric.to_string(),
+        metric_value: value,
+        version: "1.80.1".to_string(),
+        wos,
+        ..test_payload()
+    };
+    let mut first = event(&format!("{}.a", prefix), 1, "release", Some(22));
+    first.extra.insert("ab_group".to_string(), json!("control"));
+    let events = vec![
+        first,
+        event(&format!("{}.b", prefix), 2, "release", None),
+        event(&format!("{}.c", prefix), 3, "release", Some(23)),
+        event(&format!("{}.d", prefix), 4, "nightly", Some(23)),
+        event(&format!("{}_x", prefix), 5, "release", Some(23)),
+    ];
+    insert_events_unnest(pool.clone(), events).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let today = now.date_naive();
+    let options = |format| ExportOptions {
+        from: today,
+        to: today,
+        channel: Some("release".to_string()),
+        metric: Some(format!("{}.*", prefix)),
+        format,
+        chunk_size: 2,
+    };
+
+    let parquet_path = dir.path().join("events.parquet");
+    let written = export::export(&pool, &options(ExportFormat::Parquet), &parquet_path).await.unwrap();
+    assert_eq!(written, 3);
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&parquet_path).unwrap()).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 2);
+    assert_eq!(reader.schema().as_ref(), export::schema().as_ref());
+    let batches: Vec<_> = reader.build().unwrap().map(|b| b.unwrap()).collect();
+    let column = |name: &str| -> Vec<_> { batches.iter().map(|b| b.column_by_name(name).unwrap().clone()).collect() };
+    let values: Vec<i32> = column("metric_value")
+        .iter()
+        .flat_map(|c| c.as_any().downcast_ref::<Int32Array>().unwrap().values().to_vec())
+        .collect();
+    assert_eq!(values, vec![1, 2, 3]);
+    let wos: Vec<Option<i16>> = column("wos")
+        .iter()
+        .flat_map(|c| c.as_any().downcast_ref::<Int16Array>().unwrap().iter().collect::<Vec<_>>())
+        .collect();
+    assert_eq!(wos, vec![Some(22), None, Some(23)]);
+    let extra: Vec<Option<String>> = column("extra")
+        .iter()
+        .flat_map(|c| {
+            let c = c.as_any().downcast_ref::<StringArray>().unwrap();
+            c.iter().map(|v| v.map(str::to_string)).collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(extra, vec![Some(r#"{"ab_group": "control"}"#.to_string()), None, None]);
+
+    let csv_path = dir.path().join("events.csv");
+    let written = export::export(&pool, &options(ExportFormat::Csv), &csv_path).await.unwrap();
+    assert_eq!(written, 3);
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,received_at,cadence,channel,country_code,metric_name,metric_value,platform,version,woi,wos,yoi,yos,extra"
+    );
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].ends_with(r#","{""ab_group"": ""control""}""#));
+    assert!(lines[2].contains(&format!(",typical,release,TH,{}.b,2,linux,1.80.1,21,,2025,2025,", prefix)));
+
+    let upload_dir = tempfile::tempdir().unwrap();
+    let location = export::upload(&csv_path, &format!("file://{}/exports", upload_dir.path().display()))
+        .await
+        .unwrap();
+    assert_eq!(location.filename(), Some("events.csv"));
+    assert_eq!(std::fs::read_to_string(upload_dir.path().join("exports/events.csv")).unwrap(), csv);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name LIKE $1")
+        .bind(format!("{}%", prefix))
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn export_days_are_utc_days_in_any_session_time_zone() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db_in_time_zone(2, "Asia/Bangkok").await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let prefix = format!("Test.ExportZone{:08x}", rand::random::<u32>());
+    let event = |metric_name: String| MyPayload { metric_name, version: "1.80.1".to_string(), ..test_payload() };
+    let (before, during) = (format!("{}.before", prefix), format!("{}.during", prefix));
+    insert_events_unnest(pool.clone(), vec![event(before.clone()), event(during.clone())]).await.unwrap();
+    // Late on the UTC day before, and late on the UTC day itself.
+    for (metric, received_at) in [(&before, "2025-04-30T20:00:00Z"), (&during, "2025-05-01T20:00:00Z")] {
+        sqlx::query(
+            "UPDATE telemetry_event_facts SET received_at = $2::timestamptz \
+             WHERE metric_name_id = (SELECT id FROM telemetry_dim_metric_name WHERE value = $1)",
+        )
+        .bind(metric)
+        .bind(received_at)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.csv");
+    let may_day = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+    let options = ExportOptions {
+        from: may_day,
+        to: may_day,
+        channel: None,
+        metric: Some(format!("{}.*", prefix)),
+        format: ExportFormat::Csv,
+        chunk_size: 10,
+    };
+    assert_eq!(export::export(&pool, &options, &path).await.unwrap(), 1);
+    assert!(std::fs::read_to_string(&path).unwrap().contains(&during));
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name LIKE $1")
+        .bind(format!("{}%", prefix))
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn export_rejects_reversed_range() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(1).await;
+    let pool = DBPool::from(pg_pool);
+    let today = Utc::now().date_naive();
+    let options = ExportOptions {
+        from: today,
+        to: today.pred_opt().unwrap(),
+        channel: None,
+        metric: None,
+        format: ExportFormat::Csv,
+        chunk_size: 10,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    assert!(export::export(&pool, &options, &dir.path().join("events.csv")).await.is_err());
+}
@@ -0,0 +1,100 @@
+#!/usr/bin/env python3
+"""Writes GeoIP2-Country-Test.mmdb, a tiny IPv4 MaxMind DB used by
+tests/geoip_tests.rs. Run from this directory to regenerate it."""
+
+import ipaddress
+import struct
+
+NETWORKS = {
+    "203.0.113.0/24": "TH",
+    "198.51.100.0/24": "US",
+    "192.0.2.0/24": "DE",
+}
+RECORD_SIZE = 24
+
+
+def encode_uint(type_num, value):
+    body = value.to_bytes((value.bit_length() + 7) // 8, "big") if value else b""
+    return control(type_num, len(body)) + body
+
+
+def control(type_num, size):
+    assert size < 29
+    if type_num <= 7:
+        return bytes([(type_num << 5) | size])
+    return bytes([size, type_num - 7])
+
+
+def encode(value):
+    if isinstance(value, str):
+        data = value.encode()
+        return control(2, len(data)) + data
+    if isinstance(value, dict):
+        out = control(7, len(value))
+        for key, item in value.items():
+            out += encode(key) + encode(item)
+        return out
+    if isinstance(value, list):
+        out = control(11, len(value))
+        for item in value:
+            out += encode(item)
+        return out
+    raise TypeError(value)
+
+
+def main():
+    data = b""
+    offsets = {}
+    for code in sorted(set(NETWORKS.values())):
+        offsets[code] = len(data)
+        data += encode({"country": {"iso_code": code}})
+
+    # Each node is [left, right]; ints are child nodes, ("data", code) leaves.
+    nodes = [[None, None]]
+    for network, code in NETWORKS.items():
+        net = ipaddress.ip_network(network)
+        bits = int(net.network_address)
+        node = 0
+        for depth in range(net.prefixlen):
+            bit = (bits >> (31 - depth)) & 1
+            if depth == net.prefixlen - 1:
+                nodes[node][bit] = ("data", code)
+            else:
+                if nodes[node][bit] is None:
+                    nodes.append([None, None])
+                    nodes[node][bit] = len(nodes) - 1
+                node = nodes[node][bit]
+
+    node_count = len(nodes)
+
+    def record(value):
+        if value is None:
+            return node_count
+        if isinstance(value, tuple):
+            return node_count + 16 + offsets[value[1]]
+        return value
+
+    tree = b""
+    for left, right in nodes:
+        tree += record(left).to_bytes(3, "big") + record(right).to_bytes(3, "big")
+
+    metadata = b"\xab\xcd\xefMaxMind.com" + control(7, 9)
+    for key, value in [
+        ("binary_format_major_version", encode_uint(5, 2)),
+        ("binary_format_minor_version", encode_uint(5, 0)),
+        ("build_epoch", control(9, 4) + struct.pack(">I", 1750000000)),
+        ("database_type", encode("GeoIP2-Country")),
+        ("description", encode({"en": "Test fixture"})),
+        ("ip_version", encode_uint(5, 4)),
+        ("languages", encode(["en"])),
+        ("node_count", encode_uint(6, node_count)),
+        ("record_size", encode_uint(5, RECORD_SIZE)),
+    ]:
+        metadata += encode(key) + value
+
+    with open("GeoIP2-Country-Test.mmdb", "wb") as f:
+        f.write(tree + b"\0" * 16 + data + metadata)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,85 @@
+// tests/geoip_tests.rs
+//
+// tests/fixtures/GeoIP2-Country-Test.mmdb maps 203.0.113.0/24 to TH,
+// 198.51.100.0/24 to US and 192.0.2.0/24 to DE; see make_geoip_fixture.py.
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use std::path::Path;
+
+use telemetry_events::geoip::{CountryPolicy, GeoIp};
+use telemetry_events::payload::MyPayload;
+use telemetry_events::queue_job::{queue_job, EventQueue};
+use telemetry_events::wal;
+
+use common::{recent_payload, start_worker, unused_pool};
+
+const FIXTURE: &str = "tests/fixtures/GeoIP2-Country-Test.mmdb";
+
+fn geoip(policy: CountryPolicy) -> GeoIp {
+    GeoIp::open(Path::new(FIXTURE), policy).unwrap()
+}
+
+fn payload(country_code: &str) -> MyPayload {
+    MyPayload { country_code: country_code.to_string(), ..recent_payload() }
+}
+
+fn country_after(policy: CountryPolicy, client: &str, ip: &str) -> Result<String, String> {
+    let mut payload = payload(client);
+    geoip(policy)
+        .apply(&mut payload, Some(ip.parse().unwrap()))
+        .map(|_| payload.country_code)
+        .map_err(|e| e.to_string())
+}
+
+#[actix_web::test]
+async fn fixture_lookups() {
+    let geoip = geoip(CountryPolicy::PreferClient);
+    assert_eq!(geoip.country("203.0.113.9".parse().unwrap()).as_deref(), Some("TH"));
+    assert_eq!(geoip.country("198.51.100.200".parse().unwrap()).as_deref(), Some("US"));
+    assert_eq!(geoip.country("10.0.0.1".parse().unwrap()), None);
+}
+
+#[actix_web::test]
+async fn policies_decide_between_client_and_server() {
+    assert_eq!(country_after(CountryPolicy::PreferClient, "JP", "203.0.113.9"), Ok("JP".to_string()));
+    assert_eq!(country_after(CountryPolicy::PreferClient, "--", "203.0.113.9"), Ok("TH".to_string()));
+    assert_eq!(country_after(CountryPolicy::PreferServer, "JP", "203.0.113.9"), Ok("TH".to_string()));
+    assert_eq!(country_after(CountryPolicy::PreferServer, "JP", "10.0.0.1"), Ok("JP".to_string()));
+    assert_eq!(country_after(CountryPolicy::Validate, "th", "203.0.113.9"), Ok("th".to_string()));
+    assert!(country_after(CountryPolicy::Validate, "JP", "203.0.113.9").is_err());
+}
+
+#[actix_web::test]
+async fn queued_events_carry_server_country_but_no_address() {
+    let wal_dir = tempfile::tempdir().unwrap();
+    // Nothing reaches the database below BATCH_SIZE events, so the pool is never used.
+    let worker_addr = start_worker(unused_pool(), Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .app_data(web::Data::new(geoip(CountryPolicy::Validate)))
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+    let post = |country: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/p3a")
+            .peer_addr("192.0.2.44:443".parse().unwrap())
+            // Ignored, the peer isn't a trusted proxy.
+            .insert_header(("X-Forwarded-For", "203.0.113.9"))
+            .set_json(payload(country))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, post("TH")).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::call_service(&app, post("")).await.status(), StatusCode::OK);
+
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    let stored = std::fs::read_to_string(&segments[0]).unwrap();
+    let events = wal::read_segment(&segments[0]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].country_code, "DE");
+    assert!(!stored.contains("192.0.2"));
+}
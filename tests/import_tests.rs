@@ -0,0 +1,93 @@
+// tests/import_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use serde_json::Value;
+
+use telemetry_events::auth::{AuthMiddleware, SERVICE_KEY_HEADER};
+use telemetry_events::models::DBPool;
+use telemetry_events::omaha::import::import_catalog;
+use telemetry_events::omaha::Catalog;
+
+use common::{connect_test_db, test_db_configured};
+
+const TEST_SERVICE_KEY: &str = "test-service-key";
+
+fn import_resource() -> actix_web::Resource<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    web::resource("/api/v1/import")
+        .wrap(AuthMiddleware::with_key(TEST_SERVICE_KEY))
+        .route(web::get().to(import_catalog))
+        .route(web::post().to(import_catalog))
+}
+
+#[actix_web::test]
+async fn import_requires_service_key() {
+    let app = test::init_service(App::new().service(import_resource())).await;
+
+    let req = test::TestRequest::post().uri("/api/v1/import").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/import")
+        .insert_header((SERVICE_KEY_HEADER, "wrong-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn import_reports_inserted_then_unchanged() {
+    if !test_db_configured() {
+        return;
+    }
+    let pool = connect_test_db(2).await;
+    sqlx::query("TRUNCATE extension_apps CASCADE").execute(&pool).await.unwrap();
+
+    let catalog = web::Data::new(Catalog::default());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(DBPool::from(pool)))
+            .app_data(catalog.clone())
+            .service(import_resource()),
+    )
+    .await;
+    let document = std::fs::read("extensions.json").unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/import")
+        .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+        .set_payload(document.clone())
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["apps"]["inserted"], 11);
+    assert_eq!(report["versions"]["inserted"], 11);
+    assert_eq!(catalog.len(), 11);
+
+    // Re-importing the same document via the default file source changes nothing.
+    let req = test::TestRequest::get()
+        .uri("/api/v1/import?source=extensions.json")
+        .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["apps"]["unchanged"], 11);
+    assert_eq!(report["versions"]["unchanged"], 11);
+    assert_eq!(report["packages"]["inserted"], 0);
+    assert_eq!(report["packages"]["updated"], 0);
+
+    // Local sources are limited to EXTENSIONS_PATH and IMPORT_SOURCE_DIR.
+    for source in ["Cargo.toml", "/etc/passwd", "/no/such/file.json"] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/import?source={}", source))
+            .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,166 @@
+// tests/omaha_tests.rs
+
+mod common;
+
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use telemetry_events::omaha::measurement::ResultBucket;
+use telemetry_events::omaha::Catalog;
+use telemetry_events::queue_job::EventQueue;
+use telemetry_events::registry::{MetricRegistry, RegistryMode};
+use telemetry_events::routers::update_scope;
+use telemetry_events::wal;
+
+use common::{start_worker, unused_pool};
+
+#[actix_web::test]
+async fn update_check_answers_per_appid_and_version() {
+    let catalog = Catalog::load(Path::new("extensions.json")).unwrap();
+    assert_eq!(catalog.len(), 11);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(catalog))
+            .service(update_scope()),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/service/update2/json")
+        .set_json(json!({
+            "request": {
+                "protocol": "3.1",
+                "@os": "mac",
+                "app": [
+                    {"appid": "hfnkpimlhhgieaddgfemjhofmfblmnib", "version": "9000", "ping": {"r": 1}, "updatecheck": {}},
+                    {"appid": "jflhchccmppkfebkiaminageehmchikm", "version": "2025.6.4.1", "updatecheck": {}},
+                    {"appid": "unknownappidunknownappidunknowna", "version": "1.0", "updatecheck": {}}
+                ]
+            }
+        }))
+        .to_request();
+    let resp: Value = test::call_and_read_body_json(&app, req).await;
+    let apps = resp["response"]["app"].as_array().unwrap();
+
+    assert_eq!(apps[0]["status"], "ok");
+    assert_eq!(apps[0]["cohortname"], "Auto full");
+    assert_eq!(apps[0]["ping"]["status"], "ok");
+    assert_eq!(apps[0]["updatecheck"]["status"], "ok");
+    assert_eq!(apps[0]["updatecheck"]["manifest"]["version"], "9841");
+    assert_eq!(apps[0]["updatecheck"]["urls"]["url"].as_array().unwrap().len(), 6);
+    assert_eq!(
+        apps[0]["updatecheck"]["manifest"]["packages"]["package"][0]["hash_sha256"],
+        "afbff65d8d1e47cb640f8558cc1cfc15c3bcb04e3aa27c7dae74ae708c95bbc2"
+    );
+
+    assert_eq!(apps[1]["updatecheck"]["status"], "noupdate");
+    assert!(apps[1]["updatecheck"].get("manifest").is_none());
+
+    assert_eq!(apps[2]["status"], "error-unknownApplication");
+}
+
+#[actix_web::test]
+async fn update_check_rejects_other_protocol_versions() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Catalog::default()))
+            .service(update_scope()),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/service/update2/json")
+        .set_json(json!({"request": {"protocol": "3.0", "app": []}}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn pings_and_events_are_queued_as_telemetry() {
+    let wal_dir = tempfile::tempdir().unwrap();
+    // Nothing reaches the database below BATCH_SIZE events, so the pool is never used.
+    let worker_addr = start_worker(unused_pool(), Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Catalog::load(Path::new("extensions.json")).unwrap()))
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .app_data(web::Data::new(MetricRegistry::load(Path::new("metrics.json"), RegistryMode::Reject).unwrap()))
+            .service(update_scope()),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/service/update2/json")
+        .set_json(json!({
+            "request": {
+                "protocol": "3.1",
+                "@os": "Win",
+                "prodchannel": "beta",
+                "prodversion": "1.80.113",
+                "app": [{
+                    "appid": "hfnkpimlhhgieaddgfemjhofmfblmnib",
+                    "version": "9840",
+                    "ping": {"r": 1},
+                    "event": [
+                        {"eventtype": 3, "eventresult": 1, "previousversion": "9839", "nextversion": "9840"},
+                        {"eventtype": 14, "eventresult": 0, "errorcode": -1},
+                        {"eventtype": 9999, "eventresult": 4}
+                    ]
+                }, {
+                    "appid": "unknownappidunknownappidunknowna",
+                    "version": "1.0",
+                    "ping": {"r": 1}
+                }]
+            }
+        }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    let events = wal::read_segment(&segments[0]).unwrap();
+    let summary: Vec<(&str, i32)> = events
+        .iter()
+        .map(|e| (e.metric_name.as_str(), e.metric_value))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("Omaha.hfnkpimlhhgieaddgfemjhofmfblmnib.Ping", 1),
+            ("Omaha.hfnkpimlhhgieaddgfemjhofmfblmnib.Update", ResultBucket::Success as i32),
+            ("Omaha.hfnkpimlhhgieaddgfemjhofmfblmnib.Download", ResultBucket::Error as i32),
+            ("Omaha.hfnkpimlhhgieaddgfemjhofmfblmnib.Other", ResultBucket::Cancelled as i32),
+        ]
+    );
+    assert!(events.iter().all(|e| e.platform == "win" && e.channel == "beta" && e.version == "1.80.113"));
+    assert!(events.iter().all(|e| e.extra.get("component_version") == Some(&json!("9840"))));
+    assert!(events.iter().all(|e| e.woi.is_none() && e.yoi.is_none()));
+}
+
+#[actix_web::test]
+async fn measurements_failing_validation_are_not_queued() {
+    let wal_dir = tempfile::tempdir().unwrap();
+    let worker_addr = start_worker(unused_pool(), Some(wal_dir.path()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Catalog::load(Path::new("extensions.json")).unwrap()))
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            // No Omaha metrics are registered.
+            .app_data(web::Data::new(MetricRegistry::new(Vec::new(), RegistryMode::Reject).unwrap()))
+            .service(update_scope()),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/service/update2/json")
+        .set_json(json!({
+            "request": {
+                "protocol": "3.1",
+                "app": [{"appid": "hfnkpimlhhgieaddgfemjhofmfblmnib", "version": "9840", "ping": {"r": 1}}]
+            }
+        }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let segments = wal::pending_segments(wal_dir.path()).unwrap();
+    assert!(wal::read_segment(&segments[0]).unwrap().is_empty());
+}
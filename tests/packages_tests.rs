@@ -0,0 +1,165 @@
+// tests/packages_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use telemetry_events::auth::{AuthMiddleware, SERVICE_KEY_HEADER};
+use telemetry_events::models::DBPool;
+use telemetry_events::omaha::import::import_entries;
+use telemetry_events::omaha::packages::{upload_package, PackageStore};
+use telemetry_events::omaha::{parse_document, Catalog};
+use telemetry_events::routers::{crx_scope, update_scope};
+
+use common::{connect_test_db, test_db_configured};
+
+const APPID: &str = "aaaabbbbccccddddeeeeffffgggghhhh";
+const VERSION: &str = "1.0.0";
+const NAME: &str = "test_package.crx3";
+const PACKAGE: &[u8] = b"Cr24 not really a crx package";
+const TEST_SERVICE_KEY: &str = "test-service-key";
+
+fn catalog_document() -> Vec<u8> {
+    serde_json::to_vec(&json!([{
+        "response": {
+            "server": "prod",
+            "protocol": "3.1",
+            "app": [{
+                "appid": APPID,
+                "status": "ok",
+                "updatecheck": {
+                    "status": "ok",
+                    "urls": {"url": [{"codebase": "https://upstream.example/crx/"}]},
+                    "manifest": {
+                        "version": VERSION,
+                        "packages": {"package": [{
+                            "hash_sha256": format!("{:x}", Sha256::digest(PACKAGE)),
+                            "size": PACKAGE.len(),
+                            "name": NAME,
+                            "fp": format!("1.{:x}", Sha256::digest(PACKAGE)),
+                            "required": true
+                        }]}
+                    }
+                }
+            }]
+        }
+    }]))
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn download_supports_byte_ranges() {
+    let dir = tempfile::tempdir().unwrap();
+    let package_dir = dir.path().join(APPID).join(VERSION);
+    std::fs::create_dir_all(&package_dir).unwrap();
+    std::fs::write(package_dir.join(NAME), PACKAGE).unwrap();
+    let store = PackageStore::from_url(&format!("file://{}", dir.path().display())).unwrap();
+    assert_eq!(
+        store.hosted_packages().await.unwrap(),
+        vec![(APPID.to_string(), VERSION.to_string(), NAME.to_string())]
+    );
+    let app = test::init_service(App::new().app_data(web::Data::new(store)).service(crx_scope())).await;
+    let uri = format!("/crx/{}/{}/{}", APPID, VERSION, NAME);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("accept-ranges").unwrap(), "bytes");
+    assert_eq!(test::read_body(resp).await, PACKAGE);
+
+    let req = test::TestRequest::get().uri(&uri).insert_header(("Range", "bytes=5-7")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resp.headers().get("content-range").unwrap().to_str().unwrap(), format!("bytes 5-7/{}", PACKAGE.len()));
+    assert_eq!(test::read_body(resp).await, &PACKAGE[5..8]);
+
+    let req = test::TestRequest::get().uri(&uri).insert_header(("Range", "bytes=1000-")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    let missing = format!("/crx/{}/{}/missing.crx3", APPID, VERSION);
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&missing).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn update_check_lists_hosted_codebase_first() {
+    let catalog = web::Data::new(
+        Catalog::new(parse_document(&catalog_document()).unwrap())
+            .with_public_base_url(Some("https://updates.example/".to_string())),
+    );
+    let app = test::init_service(App::new().app_data(catalog.clone()).service(update_scope())).await;
+    let update_check = || {
+        test::TestRequest::post()
+            .uri("/service/update2/json")
+            .set_json(json!({"request": {"protocol": "3.1", "app": [{"appid": APPID, "version": "0.9", "updatecheck": {}}]}}))
+            .to_request()
+    };
+
+    // Not uploaded yet: only the upstream codebase is offered.
+    let resp: Value = test::call_and_read_body_json(&app, update_check()).await;
+    let urls = &resp["response"]["app"][0]["updatecheck"]["urls"]["url"];
+    assert_eq!(urls, &json!([{"codebase": "https://upstream.example/crx/"}]));
+
+    catalog.mark_hosted(APPID, VERSION, NAME);
+    let resp: Value = test::call_and_read_body_json(&app, update_check()).await;
+    let urls = &resp["response"]["app"][0]["updatecheck"]["urls"]["url"];
+    assert_eq!(urls[0]["codebase"], format!("https://updates.example/crx/{}/{}/", APPID, VERSION));
+    assert_eq!(urls[1]["codebase"], "https://upstream.example/crx/");
+}
+
+#[actix_web::test]
+async fn upload_verifies_hash_and_size() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = DBPool::from(pg_pool.clone());
+    import_entries(&pool, &parse_document(&catalog_document()).unwrap())
+        .await
+        .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = PackageStore::from_url(&format!("file://{}", dir.path().display())).unwrap();
+    let entries = parse_document(&catalog_document()).unwrap();
+    let catalog = web::Data::new(Catalog::new(entries.clone()));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(catalog.clone())
+            .app_data(web::Data::new(store))
+            .service(
+                web::resource("/api/v1/crx/{appid}/{version}/{name}")
+                    .wrap(AuthMiddleware::with_key(TEST_SERVICE_KEY))
+                    .route(web::put().to(upload_package)),
+            ),
+    )
+    .await;
+    let uri = format!("/api/v1/crx/{}/{}/{}", APPID, VERSION, NAME);
+    let upload = |body: &'static [u8]| {
+        test::TestRequest::put()
+            .uri(&uri)
+            .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, upload(b"Cr24 not really a crx PACKAGE")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, upload(b"Cr24 too short")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(!dir.path().join(APPID).exists());
+    assert!(!catalog.is_hosted(&entries[0]));
+
+    let resp = test::call_service(&app, upload(PACKAGE)).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(std::fs::read(dir.path().join(APPID).join(VERSION).join(NAME)).unwrap(), PACKAGE);
+    assert!(catalog.is_hosted(&entries[0]));
+
+    sqlx::query("DELETE FROM extension_apps WHERE appid = $1")
+        .bind(APPID)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
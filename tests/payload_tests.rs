@@ -0,0 +1,145 @@
+// tests/payload_tests.rs
+
+mod common;
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::{ExtraKeys, MyPayload};
+use telemetry_events::telemetry_event::{insert_events_copy, insert_events_unnest};
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+const METRIC_NAME: &str = "Test.Extra";
+
+/// Parsed from JSON, so `extra` goes through the same path as a submission.
+fn payload(extra: Value) -> MyPayload {
+    let mut value = serde_json::to_value(MyPayload { metric_name: METRIC_NAME.to_string(), ..test_payload() }).unwrap();
+    value.as_object_mut().unwrap().extend(extra.as_object().cloned().unwrap_or_default());
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn unknown_fields_are_kept_as_extras() {
+    let p: MyPayload = serde_json::from_value(json!({
+        "cadence": "typical", "channel": "release", "country_code": "TH",
+        "metric_name": METRIC_NAME, "metric_value": 1, "platform": "linux",
+        "version": "1.0", "woi": 21, "wos": null, "yoi": 2025, "yos": 2025,
+        "idempotency_key": "k", "arch": "arm64", "ref": {"code": "BRV001"}
+    }))
+    .unwrap();
+    assert_eq!(p.idempotency_key.as_deref(), Some("k"));
+    assert_eq!(Value::Object(p.extra.clone()), json!({"arch": "arm64", "ref": {"code": "BRV001"}}));
+
+    // Extras survive the JSON used by the WAL, archive and AMQP.
+    let round_trip: MyPayload = serde_json::from_slice(&serde_json::to_vec(&p).unwrap()).unwrap();
+    assert_eq!(round_trip.extra, p.extra);
+
+    let mut kept = p.clone();
+    ExtraKeys::parse("arch, os").retain(&mut kept);
+    assert_eq!(Value::Object(kept.extra), json!({"arch": "arm64"}));
+    let mut all = p.clone();
+    ExtraKeys::parse("*").retain(&mut all);
+    assert_eq!(all.extra.len(), 2);
+    let mut none = p;
+    ExtraKeys::parse("").retain(&mut none);
+    assert!(none.extra.is_empty());
+}
+
+#[actix_web::test]
+async fn extras_are_written_to_jsonb_column() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(METRIC_NAME)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+
+    insert_events_unnest(pool.clone(), vec![payload(json!({"arch": "arm64"})), payload(json!({}))])
+        .await
+        .unwrap();
+    insert_events_copy(pool, vec![payload(json!({"arch": "x64", "n": 2})), payload(json!({}))])
+        .await
+        .unwrap();
+
+    let rows: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT extra::text FROM telemetry_events WHERE metric_name = $1 ORDER BY id")
+            .bind(METRIC_NAME)
+            .fetch_all(&pg_pool)
+            .await
+            .unwrap();
+    let rows: Vec<Option<Value>> = rows.iter().map(|r| r.as_deref().map(|r| serde_json::from_str(r).unwrap())).collect();
+    assert_eq!(rows, vec![Some(json!({"arch": "arm64"})), None, Some(json!({"arch": "x64", "n": 2})), None]);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(METRIC_NAME)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
+
+type EventRow = (String, String, String, i32, String, String, Option<i16>, Option<i16>, Option<i16>, i16);
+
+#[actix_web::test]
+async fn inserted_rows_read_back_unchanged() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = "Test.RoundTrip";
+    let event = |metric_value: i32, wos: Option<i16>| {
+        let mut p = payload(json!({}));
+        p.metric_name = metric_name.to_string();
+        p.metric_value = metric_value;
+        p.wos = wos;
+        p
+    };
+    let events = vec![event(i32::MAX, Some(53)), event(-7, None), event(0, Some(1))];
+
+    insert_events_unnest(pool.clone(), events.clone()).await.unwrap();
+    insert_events_copy(pool, events.clone()).await.unwrap();
+
+    let rows: Vec<EventRow> = sqlx::query_as(
+        r#"
+        SELECT cadence, channel, country_code, metric_value, platform, version, woi, wos, yoi, yos
+        FROM telemetry_events
+        WHERE metric_name = $1
+        ORDER BY id
+        "#,
+    )
+    .bind(metric_name)
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap();
+    let expected: Vec<EventRow> = events
+        .iter()
+        .chain(events.iter())
+        .map(|e| {
+            (
+                e.cadence.clone(),
+                e.channel.clone(),
+                e.country_code.clone(),
+                e.metric_value,
+                e.platform.clone(),
+                e.version.clone(),
+                e.woi,
+                e.wos,
+                e.yoi,
+                e.yos,
+            )
+        })
+        .collect();
+    assert_eq!(rows, expected);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
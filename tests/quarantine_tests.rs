@@ -0,0 +1,174 @@
+// tests/quarantine_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::{Datelike, Utc};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+use telemetry_events::auth::{AuthMiddleware, SERVICE_KEY_HEADER};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::quarantine::{browse_quarantine, resubmit_quarantine};
+use telemetry_events::queue_job::{queue_job, EventQueue};
+use telemetry_events::registry::{MetricDefinition, MetricRegistry, RegistryMode};
+
+use common::{connect_test_db, recent_payload, start_worker, test_db_configured};
+
+const TEST_SERVICE_KEY: &str = "test-service-key";
+
+/// A payload surveyed this week.
+fn payload(metric_name: &str, woi: i16, yoi: i16) -> MyPayload {
+    MyPayload { metric_name: metric_name.to_string(), woi: Some(woi), yoi: Some(yoi), ..recent_payload() }
+}
+
+#[actix_web::test]
+async fn rejected_events_are_quarantined_and_resubmitted() {
+    if !test_db_configured() {
+        return;
+    }
+    let pool = connect_test_db(2).await;
+    let db_pool = Arc::new(DBPool::from(pool.clone()));
+    sqlx::query("DELETE FROM telemetry_quarantine").execute(&pool).await.unwrap();
+    let worker_addr = start_worker(pool.clone(), None);
+    let queue = web::Data::new(EventQueue::Worker(worker_addr));
+    let strict = web::Data::new(MetricRegistry::load(Path::new("metrics.json"), RegistryMode::Reject).unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(queue.clone())
+            .app_data(strict)
+            .app_data(web::Data::from(db_pool.clone()))
+            .service(
+                web::scope("/api/v1/quarantine")
+                    .wrap(AuthMiddleware::with_key(TEST_SERVICE_KEY))
+                    .route("", web::get().to(browse_quarantine)),
+            )
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/p3a")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload("{\"metric_name\": ")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_json(payload("Brave.New.Metric", 21, 2025)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_json(payload("Brave.Core.UsageDaily", 1, 2999)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get().uri("/api/v1/quarantine").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/quarantine")
+        .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+        .to_request();
+    let listed: Value = test::call_and_read_body_json(&app, req).await;
+    let reasons: Vec<&str> = listed.as_array().unwrap().iter().map(|e| e["reason"].as_str().unwrap()).collect();
+    assert_eq!(reasons, vec!["future_install", "unknown_metric", "invalid_json"]);
+    assert_eq!(listed[2]["raw"], "{\"metric_name\": ");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/quarantine?reason=unknown_metric")
+        .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+        .to_request();
+    let listed: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    let unknown_id = listed[0]["id"].as_i64().unwrap();
+
+    // The metric gets registered, so a resubmission now passes.
+    let mut definitions = MetricRegistry::load(Path::new("metrics.json"), RegistryMode::Reject)
+        .unwrap()
+        .definitions();
+    definitions.push(MetricDefinition {
+        name: "Brave.New.Metric".to_string(),
+        min_value: 0,
+        max_value: 1,
+        cadences: vec!["typical".to_string()],
+        owner: "test".to_string(),
+        expires: None,
+        description: None,
+    });
+    let updated = web::Data::new(MetricRegistry::new(definitions, RegistryMode::Reject).unwrap());
+    let admin = test::init_service(
+        App::new()
+            .app_data(queue)
+            .app_data(updated)
+            .app_data(web::Data::from(db_pool))
+            .route("/api/v1/quarantine/resubmit", web::post().to(resubmit_quarantine)),
+    )
+    .await;
+    let req = test::TestRequest::post().uri("/api/v1/quarantine/resubmit").set_json(json!({})).to_request();
+    assert_eq!(test::call_service(&admin, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/quarantine/resubmit")
+        .set_json(json!({"reason": "unknown_metric"}))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&admin, req).await;
+    assert_eq!(report, json!({"resubmitted": 1, "rejected": 0}));
+    let req = test::TestRequest::post()
+        .uri("/api/v1/quarantine/resubmit")
+        .set_json(json!({"reason": "future_install"}))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&admin, req).await;
+    assert_eq!(report, json!({"resubmitted": 0, "rejected": 1}));
+    let req = test::TestRequest::post()
+        .uri("/api/v1/quarantine/resubmit")
+        .set_json(json!({"reason": "country_mismatch"}))
+        .to_request();
+    assert_eq!(test::call_service(&admin, req).await.status(), StatusCode::BAD_REQUEST);
+
+    // Resubmissions are stored as received now, so a survey that was recent
+    // when the row arrived can be stale by the time it is resubmitted.
+    let arrived = Utc::now() - chrono::Duration::weeks(20);
+    let mut old = payload("Brave.New.Metric", 21, 2025);
+    let survey = arrived.date_naive().iso_week();
+    (old.wos, old.yos) = (Some(survey.week() as i16), survey.year() as i16);
+    let old_id: i64 = sqlx::query_scalar(
+        "INSERT INTO telemetry_quarantine (reason, detail, raw, received_at) VALUES ('unknown_metric', '', $1, $2) RETURNING id",
+    )
+    .bind(serde_json::to_string(&old).unwrap())
+    .bind(arrived)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/v1/quarantine/resubmit")
+        .set_json(json!({"ids": [old_id]}))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&admin, req).await;
+    assert_eq!(report, json!({"resubmitted": 0, "rejected": 1}));
+    let reason: String = sqlx::query_scalar("SELECT reason FROM telemetry_quarantine WHERE id = $1")
+        .bind(old_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(reason, "stale_survey");
+
+    let resubmitted: Option<chrono::DateTime<chrono::Utc>> =
+        sqlx::query_scalar("SELECT resubmitted_at FROM telemetry_quarantine WHERE id = $1")
+            .bind(unknown_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(resubmitted.is_some());
+
+    // Past its burst a client's rejections are no longer stored.
+    for _ in 0..25 {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/p3a")
+            .peer_addr("192.0.2.200:50000".parse().unwrap())
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("{\"flood\": ")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+    let stored: i64 = sqlx::query_scalar("SELECT count(*) FROM telemetry_quarantine WHERE raw = '{\"flood\": '")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!((20..25).contains(&stored), "{}", stored);
+}
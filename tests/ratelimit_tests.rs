@@ -0,0 +1,120 @@
+// tests/ratelimit_tests.rs
+
+use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+use std::str::FromStr;
+
+use telemetry_events::metrics;
+use telemetry_events::ratelimit::{RateLimitConfig, RateLimitKey, RateLimiter};
+
+#[actix_web::test]
+async fn config_parses_key_rate_and_burst() {
+    let config = RateLimitConfig::from_str("channel:0.5:10").unwrap();
+    assert_eq!(config.key, RateLimitKey::Channel);
+    assert_eq!(config.rate, 0.5);
+    assert_eq!(config.burst, 10.0);
+    assert!(RateLimitConfig::from_str("ip:0:10").is_err());
+    assert!(RateLimitConfig::from_str("cookie:1:10").is_err());
+    assert!(RateLimitConfig::from_str("ip:1").is_err());
+}
+
+#[actix_web::test]
+async fn requests_over_the_burst_get_429_per_channel() {
+    let config = RateLimitConfig::from_str("channel:0.1:2").unwrap();
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/api/v1/{channel}")
+                .wrap(RateLimiter::new("test_channel", Some(config)))
+                .route(web::post().to(HttpResponse::Ok)),
+        ),
+    )
+    .await;
+    let post = |channel: &str| test::TestRequest::post().uri(&format!("/api/v1/{}", channel)).to_request();
+
+    for _ in 0..2 {
+        assert_eq!(test::call_service(&app, post("release")).await.status(), StatusCode::OK);
+    }
+    let resp = test::call_service(&app, post("release")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "10");
+
+    // Other channels have their own bucket.
+    assert_eq!(test::call_service(&app, post("nightly")).await.status(), StatusCode::OK);
+
+    // Unknown channels all share one bucket.
+    assert_eq!(test::call_service(&app, post("unknown-1")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, post("unknown-2")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, post("unknown-3")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    assert_eq!(
+        metrics::value("rate_limited_requests_total", &[("route", "test_channel"), ("key", "channel")]),
+        2
+    );
+    assert!(metrics::render().contains("rate_limited_requests_total{route=\"test_channel\",key=\"channel\"} 2"));
+}
+
+#[actix_web::test]
+async fn unconfigured_limiter_passes_everything() {
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/")
+                .wrap(RateLimiter::new("test_disabled", None))
+                .route(web::get().to(HttpResponse::Ok)),
+        ),
+    )
+    .await;
+    for _ in 0..50 {
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
+
+#[actix_web::test]
+async fn ip_limit_ignores_forwarded_addresses_from_untrusted_peers() {
+    let config = RateLimitConfig::from_str("ip:0.1:1").unwrap();
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/")
+                .wrap(RateLimiter::new("test_ip", Some(config)))
+                .route(web::post().to(HttpResponse::Ok)),
+        ),
+    )
+    .await;
+    let post = |peer: &str, forwarded: &str| {
+        test::TestRequest::post()
+            .uri("/")
+            .peer_addr(format!("{}:50000", peer).parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, post("192.0.2.1", "198.51.100.1")).await.status(), StatusCode::OK);
+    let resp = test::call_service(&app, post("192.0.2.1", "198.51.100.2")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(test::call_service(&app, post("192.0.2.2", "198.51.100.2")).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn service_key_limit_only_trusts_valid_keys() {
+    let config = RateLimitConfig::from_str("service_key:0.1:1").unwrap();
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/")
+                .wrap(RateLimiter::new("test_service_key", Some(config)).with_service_key("secret"))
+                .route(web::post().to(HttpResponse::Ok)),
+        ),
+    )
+    .await;
+    let post = |key: &str| {
+        test::TestRequest::post()
+            .uri("/")
+            .peer_addr("192.0.2.1:50000".parse().unwrap())
+            .insert_header(("BraveServiceKey", key))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, post("guess-1")).await.status(), StatusCode::OK);
+    // Made up keys share the bucket of their address.
+    assert_eq!(test::call_service(&app, post("guess-2")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(test::call_service(&app, post("secret")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, post("secret")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,106 @@
+// tests/registry_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::{NaiveDate, Utc};
+use serde_json::Value;
+use std::path::Path;
+
+use telemetry_events::payload::MyPayload;
+use telemetry_events::quarantine;
+use telemetry_events::queue_job::{queue_job, EventQueue};
+use telemetry_events::temporal::TemporalPolicy;
+use telemetry_events::registry::{list_metrics, MetricDefinition, MetricRegistry, MetricViolation, RegistryMode};
+
+use common::{recent_payload, start_worker, unused_pool};
+
+fn payload(metric_name: &str, metric_value: i32, cadence: &str) -> MyPayload {
+    MyPayload {
+        cadence: cadence.to_string(),
+        metric_name: metric_name.to_string(),
+        metric_value,
+        ..recent_payload()
+    }
+}
+
+fn registry(mode: RegistryMode) -> MetricRegistry {
+    let mut definitions: Vec<MetricDefinition> =
+        serde_json::from_slice(&std::fs::read("metrics.json").unwrap()).unwrap();
+    definitions.push(MetricDefinition {
+        name: "Brave.Legacy.Metric".to_string(),
+        min_value: 0,
+        max_value: 3,
+        cadences: vec!["typical".to_string()],
+        owner: "core".to_string(),
+        expires: NaiveDate::from_ymd_opt(2025, 1, 31),
+        description: None,
+    });
+    MetricRegistry::new(definitions, mode).unwrap()
+}
+
+#[actix_web::test]
+async fn registry_checks_name_range_cadence_and_expiry() {
+    let registry = registry(RegistryMode::Reject);
+    let today = NaiveDate::from_ymd_opt(2025, 6, 18).unwrap();
+    let check = |p: MyPayload| registry.check(&p, today);
+
+    assert_eq!(check(payload("Brave.Today.WeeklySessionCount", 7, "typical")), Ok(()));
+    assert_eq!(
+        check(payload("Brave.Today.WeeklySessionCount", 8, "typical")),
+        Err(MetricViolation::ValueOutOfRange { min: 0, max: 7 })
+    );
+    assert_eq!(
+        check(payload("Brave.Today.WeeklySessionCount", 1, "express")),
+        Err(MetricViolation::CadenceNotAllowed)
+    );
+    assert_eq!(check(payload("Brave.Unknown", 1, "typical")), Err(MetricViolation::UnknownMetric));
+    assert_eq!(
+        check(payload("Brave.Legacy.Metric", 1, "typical")),
+        Err(MetricViolation::Expired(NaiveDate::from_ymd_opt(2025, 1, 31).unwrap()))
+    );
+    assert_eq!(check(payload("Omaha.hfnkpimlhhgieaddgfemjhofmfblmnib.Update", 1, "typical")), Ok(()));
+    assert_eq!(check(payload("OmahaX.Update", 1, "typical")), Err(MetricViolation::UnknownMetric));
+}
+
+#[actix_web::test]
+async fn ingestion_rejects_unregistered_metrics_and_lists_registry() {
+    let worker_addr = start_worker(unused_pool(), None);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker_addr)))
+            .app_data(web::Data::new(
+                MetricRegistry::load(Path::new("metrics.json"), RegistryMode::Reject).unwrap(),
+            ))
+            .route("/api/v1/registry/metrics", web::get().to(list_metrics))
+            .route("/api/v1/{channel}", web::post().to(queue_job)),
+    )
+    .await;
+    let post = |p: MyPayload| test::TestRequest::post().uri("/api/v1/p3a").set_json(p).to_request();
+
+    let resp = test::call_service(&app, post(payload("Brave.Unknown", 1, "typical"))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, post(payload("Brave.Today.WeeklySessionCount", 3, "typical"))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/api/v1/registry/metrics").to_request();
+    let listed: Value = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = listed.as_array().unwrap().iter().map(|m| m["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Brave.Core.UsageDaily", "Brave.Today.WeeklySessionCount", "Omaha.*"]);
+}
+
+#[actix_web::test]
+async fn warn_mode_still_rejects_unknown_metrics() {
+    let registry = registry(RegistryMode::Warn);
+    let today = Utc::now().date_naive();
+    let temporal = TemporalPolicy {
+        future_tolerance_days: 1,
+        max_survey_age_weeks: 4,
+    };
+    let validate = |p: MyPayload| quarantine::validate(&p, Some(&registry), &temporal, today);
+
+    assert!(validate(payload("Brave.Today.WeeklySessionCount", 99, "typical")).is_ok());
+    assert!(validate(payload("Brave.Legacy.Metric", 1, "typical")).is_ok());
+    let rejection = validate(payload("Brave.Made.Up", 1, "typical")).unwrap_err();
+    assert_eq!(rejection.reason, "unknown_metric");
+}
@@ -0,0 +1,114 @@
+// tests/retention_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::retention::{build_matrix, get_retention, refresh, RetentionRow};
+use telemetry_events::telemetry_event::insert_events_unnest;
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+fn row(platform: &str, install_week: NaiveDate, weeks_since_install: i32, reports: i64) -> RetentionRow {
+    RetentionRow {
+        platform: platform.to_string(),
+        channel: "release".to_string(),
+        install_week,
+        weeks_since_install,
+        reports,
+    }
+}
+
+#[actix_web::test]
+async fn matrix_has_one_row_per_cohort() {
+    let may19 = NaiveDate::from_ymd_opt(2025, 5, 19).unwrap();
+    let may26 = NaiveDate::from_ymd_opt(2025, 5, 26).unwrap();
+    let matrix = build_matrix(
+        vec![
+            row("linux", may26, 0, 10),
+            row("linux", may19, 2, 5),
+            row("linux", may19, 0, 20),
+            row("linux", may19, 1, 10),
+            row("linux", may19, 9, 1),
+            row("macos", may19, 1, 3),
+        ],
+        2,
+    );
+    assert_eq!(matrix.len(), 3);
+    assert_eq!((matrix[0].platform.as_str(), matrix[0].install_week), ("linux", may19));
+    assert_eq!(matrix[0].reports, vec![20, 10, 5]);
+    assert_eq!(matrix[0].retention, vec![Some(1.0), Some(0.5), Some(0.25)]);
+    assert_eq!(matrix[1].reports, vec![10, 0, 0]);
+    // No week 0 reports, so no retention.
+    assert_eq!(matrix[2].reports, vec![0, 3, 0]);
+    assert_eq!(matrix[2].retention, vec![None, None, None]);
+}
+
+#[actix_web::test]
+async fn retention_is_served_from_refreshed_view() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = format!("Test.Retention.{:08x}", rand::random::<u32>());
+    let event = |platform: &str, woi: i16, wos: i16| MyPayload {
+        metric_name: metric_name.clone(),
+        platform: platform.to_string(),
+        version: "1.80.1".to_string(),
+        woi: Some(woi),
+        wos: Some(wos),
+        ..test_payload()
+    };
+    let mut events = Vec::new();
+    events.extend((0..4).map(|_| event("linux", 20, 20)));
+    events.extend((0..2).map(|_| event("linux", 20, 21)));
+    events.push(event("linux", 20, 23));
+    events.push(event("linux", 21, 21));
+    events.push(event("android", 20, 20));
+    // Omaha measurements have no install week and belong to no cohort.
+    events.push(MyPayload { woi: None, yoi: None, ..event("linux", 20, 21) });
+    insert_events_unnest(pool.clone(), events).await.unwrap();
+    refresh(&pool).await.unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(pool))
+            .route("/api/v1/retention", web::get().to(get_retention)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/retention?metric_name={}&platform=linux&max_weeks=3", metric_name))
+        .to_request();
+    let matrix: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        matrix,
+        json!([
+            {
+                "platform": "linux", "channel": "release", "install_week": "2025-05-12",
+                "reports": [4, 2, 0, 1], "retention": [1.0, 0.5, 0.0, 0.25]
+            },
+            {
+                "platform": "linux", "channel": "release", "install_week": "2025-05-19",
+                "reports": [1, 0, 0, 0], "retention": [1.0, 0.0, 0.0, 0.0]
+            }
+        ])
+    );
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/retention?metric_name={}&max_weeks=500", metric_name))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(&metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+    refresh(&DBPool::from(pg_pool)).await.unwrap();
+}
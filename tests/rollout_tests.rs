@@ -0,0 +1,222 @@
+// tests/rollout_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use serde_json::{json, Value};
+
+use telemetry_events::auth::{AuthMiddleware, SERVICE_KEY_HEADER};
+use telemetry_events::models::DBPool;
+use telemetry_events::omaha::import::import_entries;
+use telemetry_events::omaha::rollout::{get_rollouts, put_rollout, remove_rollout, Rollout, ANY};
+use telemetry_events::omaha::{parse_document, Catalog};
+use telemetry_events::routers::update_scope;
+
+use common::{connect_test_db, test_db_configured};
+
+const APPID: &str = "rolloutrolloutrolloutrolloutroll";
+const TEST_SERVICE_KEY: &str = "test-service-key";
+
+fn release(version: &str) -> Value {
+    json!({
+        "response": {
+            "server": "prod",
+            "protocol": "3.1",
+            "app": [{
+                "appid": APPID,
+                "cohort": "1:stable:",
+                "cohortname": "Stable",
+                "status": "ok",
+                "updatecheck": {
+                    "status": "ok",
+                    "urls": {"url": [{"codebase": "https://upstream.example/crx/"}]},
+                    "manifest": {
+                        "version": version,
+                        "packages": {"package": [{
+                            "hash_sha256": "afbff65d8d1e47cb640f8558cc1cfc15c3bcb04e3aa27c7dae74ae708c95bbc2",
+                            "size": 1,
+                            "name": format!("{}.crx3", version),
+                            "fp": "1.afbff65d",
+                            "required": true
+                        }]}
+                    }
+                }
+            }]
+        }
+    })
+}
+
+fn catalog() -> Catalog {
+    let document = serde_json::to_vec(&json!([release("1.0"), release("2.0")])).unwrap();
+    Catalog::new(parse_document(&document).unwrap())
+}
+
+fn rule(channel: &str, platform: &str, version: &str, percentage: i16, pinned: bool) -> Rollout {
+    Rollout {
+        appid: APPID.to_string(),
+        channel: channel.to_string(),
+        platform: platform.to_string(),
+        version: version.to_string(),
+        percentage,
+        pinned,
+        cohort: Some(format!("1:{}:", channel)),
+        cohortname: Some(format!("{} {}%", channel, percentage)),
+    }
+}
+
+fn update_request(channel: &str, platform: &str, userid: &str) -> Value {
+    json!({"request": {
+        "protocol": "3.1",
+        "@os": platform,
+        "prodchannel": channel,
+        "userid": userid,
+        "app": [{"appid": APPID, "version": "0.5", "updatecheck": {}}]
+    }})
+}
+
+/// Sends an update check and returns the single app in the response.
+macro_rules! offered {
+    ($app:expr, $request:expr) => {{
+        let req = test::TestRequest::post()
+            .uri("/service/update2/json")
+            .set_json($request)
+            .to_request();
+        let resp: Value = test::call_and_read_body_json($app, req).await;
+        resp["response"]["app"][0].clone()
+    }};
+}
+
+#[actix_web::test]
+async fn pinned_rule_rolls_back_one_platform() {
+    let catalog = catalog();
+    catalog.set_rollouts(vec![rule(ANY, "win", "1.0", 100, true)]);
+    let app = test::init_service(App::new().app_data(web::Data::new(catalog)).service(update_scope())).await;
+
+    let win = offered!(&app, update_request("release", "win", "a"));
+    assert_eq!(win["updatecheck"]["manifest"]["version"], "1.0");
+    assert_eq!(win["cohortname"], "* 100%");
+
+    let mac = offered!(&app, update_request("release", "mac", "a"));
+    assert_eq!(mac["updatecheck"]["manifest"]["version"], "2.0");
+    assert_eq!(mac["cohortname"], "Stable");
+}
+
+#[actix_web::test]
+async fn pinned_rule_downgrades_clients_that_allow_rollback() {
+    let catalog = catalog();
+    catalog.set_rollouts(vec![rule(ANY, ANY, "1.0", 100, true)]);
+    let app = test::init_service(App::new().app_data(web::Data::new(catalog)).service(update_scope())).await;
+    let request = |updatecheck: Value| {
+        json!({"request": {
+            "protocol": "3.1",
+            "userid": "a",
+            "app": [{"appid": APPID, "version": "2.0", "updatecheck": updatecheck}]
+        }})
+    };
+
+    let allowed = offered!(&app, request(json!({"rollback_allowed": true})));
+    assert_eq!(allowed["updatecheck"]["status"], "ok");
+    assert_eq!(allowed["updatecheck"]["manifest"]["version"], "1.0");
+
+    let not_allowed = offered!(&app, request(json!({})));
+    assert_eq!(not_allowed["updatecheck"]["status"], "noupdate");
+}
+
+#[actix_web::test]
+async fn percentage_rule_ignores_session_ids() {
+    let catalog = catalog();
+    catalog.set_rollouts(vec![rule("release", ANY, "2.0", 99, false)]);
+    let app = test::init_service(App::new().app_data(web::Data::new(catalog)).service(update_scope())).await;
+
+    for sessionid in 0..20 {
+        let response = offered!(&app, json!({"request": {
+            "protocol": "3.1",
+            "prodchannel": "release",
+            "sessionid": sessionid.to_string(),
+            "app": [{"appid": APPID, "version": "0.5", "updatecheck": {}}]
+        }}));
+        assert_eq!(response["updatecheck"]["manifest"]["version"], "1.0");
+    }
+}
+
+#[actix_web::test]
+async fn percentage_rule_splits_clients_stably() {
+    let catalog = catalog();
+    catalog.set_rollouts(vec![
+        rule("beta", ANY, "2.0", 100, false),
+        rule("release", ANY, "2.0", 30, false),
+    ]);
+    let app = test::init_service(App::new().app_data(web::Data::new(catalog)).service(update_scope())).await;
+
+    let mut new_version = 0;
+    for userid in 0..200 {
+        let first = offered!(&app, update_request("release", "linux", &userid.to_string()));
+        let again = offered!(&app, update_request("release", "linux", &userid.to_string()));
+        assert_eq!(first, again);
+        if first["updatecheck"]["manifest"]["version"] == "2.0" {
+            assert_eq!(first["cohortname"], "release 30%");
+            new_version += 1;
+        } else {
+            assert_eq!(first["updatecheck"]["manifest"]["version"], "1.0");
+        }
+    }
+    assert!((35..85).contains(&new_version), "{} of 200 clients got 2.0", new_version);
+
+    let beta = offered!(&app, update_request("beta", "linux", "0"));
+    assert_eq!(beta["updatecheck"]["manifest"]["version"], "2.0");
+}
+
+#[actix_web::test]
+async fn rollout_api_updates_served_rules() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = DBPool::from(pg_pool.clone());
+    let document = serde_json::to_vec(&json!([release("1.0"), release("2.0")])).unwrap();
+    import_entries(&pool, &parse_document(&document).unwrap()).await.unwrap();
+
+    let catalog = web::Data::new(catalog());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(catalog.clone())
+            .service(
+                web::scope("/api/v1/rollouts")
+                    .wrap(AuthMiddleware::with_key(TEST_SERVICE_KEY))
+                    .route("", web::get().to(get_rollouts))
+                    .route("", web::put().to(put_rollout))
+                    .route("/{appid}/{channel}/{platform}", web::delete().to(remove_rollout)),
+            ),
+    )
+    .await;
+    let put = |rollout: Rollout| {
+        test::TestRequest::put()
+            .uri("/api/v1/rollouts")
+            .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+            .set_json(rollout)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, put(rule("release", ANY, "3.0", 10, false))).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = test::call_service(&app, put(rule("release", ANY, "2.0", 101, false))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, put(rule("release", ANY, "2.0", 10, false))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(catalog.rollouts(APPID), vec![rule("release", ANY, "2.0", 10, false)]);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/rollouts/{}/release/*", APPID))
+        .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    assert!(catalog.rollouts(APPID).is_empty());
+
+    sqlx::query("DELETE FROM extension_apps WHERE appid = $1")
+        .bind(APPID)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,220 @@
+// tests/sink_tests.rs
+
+mod common;
+
+use actix::Actor;
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use futures_util::future::BoxFuture;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use telemetry_events::error::AppError;
+use telemetry_events::metrics;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::queue_job::EventQueue;
+use telemetry_events::routers::service_scope;
+use telemetry_events::sink::{self, EventSink, FanOut, NdjsonFileSink, SinkConfig};
+use telemetry_events::wal::{self, FsyncPolicy, Wal};
+use telemetry_events::worker::{ActorWorker, DeliveryMessage, BATCH_SIZE};
+
+use common::{recent_payload, test_payload};
+
+fn payload(metric_value: i32) -> MyPayload {
+    MyPayload { metric_value, ..test_payload() }
+}
+
+fn read_values(path: &std::path::Path) -> Vec<i32> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str::<MyPayload>(line).unwrap().metric_value)
+        .collect()
+}
+
+/// Records batches in memory, or fails every write while `fail` is set.
+#[derive(Clone)]
+struct TestSink {
+    name: &'static str,
+    fail: Arc<AtomicBool>,
+    written: Arc<Mutex<Vec<i32>>>,
+}
+
+impl TestSink {
+    fn new(name: &'static str, fail: bool) -> Self {
+        Self { name, fail: Arc::new(AtomicBool::new(fail)), written: Default::default() }
+    }
+}
+
+impl EventSink for TestSink {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn write<'a>(&'a self, events: &'a [MyPayload]) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(AppError::StorageError("unavailable".to_string()));
+            }
+            self.written.lock().unwrap().extend(events.iter().map(|e| e.metric_value));
+            Ok(())
+        })
+    }
+}
+
+#[actix_web::test]
+async fn sink_list_is_parsed() {
+    assert_eq!(
+        SinkConfig::parse_list("postgres, stdout,ndjson:/tmp/events.ndjson").unwrap(),
+        vec![
+            SinkConfig::Postgres,
+            SinkConfig::Stdout,
+            SinkConfig::Ndjson(PathBuf::from("/tmp/events.ndjson")),
+        ]
+    );
+    assert!(SinkConfig::uses_postgres(&SinkConfig::parse_list("stdout,postgres").unwrap()));
+    assert!(!SinkConfig::uses_postgres(&SinkConfig::parse_list("stdout").unwrap()));
+    assert!(SinkConfig::parse_list("").is_err());
+    assert!(SinkConfig::parse_list("ndjson:").is_err());
+    assert!(SinkConfig::parse_list("postgres,kafka").is_err());
+}
+
+#[actix_web::test]
+async fn ndjson_sink_appends_batches() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    NdjsonFileSink::open(&path).unwrap().write(&[payload(1), payload(2)]).await.unwrap();
+    let sink = NdjsonFileSink::open(&path).unwrap();
+    assert_eq!(sink.name(), format!("ndjson:{}", path.display()));
+    sink.write(&[payload(3)]).await.unwrap();
+    assert_eq!(read_values(&path), vec![1, 2, 3]);
+}
+
+#[actix_web::test]
+async fn fan_out_spools_batches_of_failing_sinks() {
+    let dir = tempfile::tempdir().unwrap();
+    let healthy = TestSink::new("fan_out_test_healthy", false);
+    let broken = TestSink::new("fan_out_test_broken", true);
+    let fan_out = FanOut::new(vec![Box::new(broken.clone()), Box::new(healthy.clone())], dir.path());
+
+    fan_out.write(&[payload(1), payload(2)]).await.unwrap();
+    fan_out.write(&[payload(3)]).await.unwrap();
+    assert_eq!(*healthy.written.lock().unwrap(), vec![1, 2, 3]);
+    assert!(broken.written.lock().unwrap().is_empty());
+    let spool = dir.path().join("fan_out_test_broken");
+    assert_eq!(wal::pending_segments(&spool).unwrap().len(), 2);
+    assert_eq!(metrics::value("sink_writes_total", &[("sink", "fan_out_test_healthy"), ("result", "ok")]), 2);
+    assert_eq!(metrics::value("sink_writes_total", &[("sink", "fan_out_test_broken"), ("result", "spooled")]), 2);
+
+    // Once the sink recovers its spooled batches are written first.
+    broken.fail.store(false, Ordering::SeqCst);
+    fan_out.write(&[payload(4)]).await.unwrap();
+    assert_eq!(*broken.written.lock().unwrap(), vec![1, 2, 3, 4]);
+    assert_eq!(*healthy.written.lock().unwrap(), vec![1, 2, 3, 4]);
+    assert!(wal::pending_segments(&spool).unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn fan_out_fails_batches_it_cannot_spool() {
+    let dir = tempfile::tempdir().unwrap();
+    // A file where the spool directory should go.
+    let spool_dir = dir.path().join("spool");
+    std::fs::write(&spool_dir, "").unwrap();
+    let healthy = TestSink::new("fan_out_unspooled_healthy", false);
+    let broken = TestSink::new("fan_out_unspooled_broken", true);
+    let fan_out = FanOut::new(vec![Box::new(broken.clone()), Box::new(healthy.clone())], &spool_dir);
+
+    let result = fan_out.write(&[payload(1)]).await;
+    assert!(matches!(result, Err(AppError::QueueError(message)) if message.contains("fan_out_unspooled_broken")));
+    assert_eq!(*healthy.written.lock().unwrap(), vec![1]);
+    assert_eq!(metrics::value("sink_writes_total", &[("sink", "fan_out_unspooled_broken"), ("result", "error")]), 1);
+}
+
+#[actix_web::test]
+async fn worker_writes_batches_to_sink_without_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let wal_dir = dir.path().join("wal");
+    let worker = ActorWorker {
+        sink: Arc::new(NdjsonFileSink::open(&path).unwrap()),
+        buffer: Default::default(),
+        wal: Some(Wal::open(&wal_dir, FsyncPolicy::Always).unwrap()),
+        archive: None,
+    }
+    .start();
+    for value in 0..BATCH_SIZE as i32 {
+        worker.send(DeliveryMessage(payload(value))).await.unwrap().unwrap();
+    }
+
+    let mut written = Vec::new();
+    for _ in 0..50 {
+        written = read_values(&path);
+        if written.len() == BATCH_SIZE {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(written, (0..BATCH_SIZE as i32).collect::<Vec<_>>());
+    // Only the fresh segment is left once the sealed one is removed.
+    let mut segments = Vec::new();
+    for _ in 0..50 {
+        segments = wal::pending_segments(&wal_dir).unwrap();
+        if segments.len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(segments.len(), 1);
+}
+
+#[actix_web::test]
+async fn only_the_postgres_sink_needs_a_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let ndjson = SinkConfig::Ndjson(dir.path().join("events.ndjson"));
+    assert!(sink::build_sink(&[SinkConfig::Stdout, ndjson], None).is_ok());
+    assert!(sink::build_sink(&[SinkConfig::Postgres], None).is_err());
+}
+
+#[actix_web::test]
+async fn submissions_are_handled_without_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let worker = ActorWorker {
+        sink: Arc::new(NdjsonFileSink::open(&path).unwrap()),
+        buffer: Default::default(),
+        wal: None,
+        archive: None,
+    }
+    .start();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(EventQueue::Worker(worker)))
+            .service(service_scope()),
+    )
+    .await;
+
+    // Rejections skip the quarantine instead of waiting for a connection.
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_json(payload(1)).to_request();
+    let response = tokio::time::timeout(Duration::from_secs(1), test::call_service(&app, req)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post().uri("/api/v1/p3a").set_json(recent_payload()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn wal_replay_writes_to_any_sink() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = Wal::open(dir.path(), FsyncPolicy::Always).unwrap();
+    wal.append(&payload(7)).unwrap();
+    wal.append(&payload(8)).unwrap();
+    wal.rotate().unwrap();
+    drop(wal);
+
+    let sink = TestSink::new("replay_test", false);
+    assert_eq!(wal::replay(dir.path(), &sink).await.unwrap(), 2);
+    assert_eq!(*sink.written.lock().unwrap(), vec![7, 8]);
+    assert_eq!(wal::replay(dir.path(), &sink).await.unwrap(), 0);
+}
@@ -0,0 +1,74 @@
+// tests/temporal_tests.rs
+
+mod common;
+
+use chrono::NaiveDate;
+
+use telemetry_events::payload::MyPayload;
+use telemetry_events::temporal::{TemporalPolicy, TemporalViolation};
+
+fn payload(woi: i16, yoi: i16, wos: Option<i16>, yos: i16) -> MyPayload {
+    MyPayload { woi: Some(woi), yoi: Some(yoi), wos, yos, ..common::test_payload() }
+}
+
+#[test]
+fn weeks_are_checked_against_each_other_and_today() {
+    let policy = TemporalPolicy {
+        future_tolerance_days: 1,
+        max_survey_age_weeks: 4,
+    };
+    // Wednesday of ISO week 25 of 2025.
+    let today = NaiveDate::from_ymd_opt(2025, 6, 18).unwrap();
+    let check = |p: MyPayload| policy.check(&p, today);
+
+    assert_eq!(check(payload(10, 2019, Some(25), 2025)), Ok(()));
+    assert_eq!(check(payload(25, 2025, Some(25), 2025)), Ok(()));
+    assert_eq!(check(payload(21, 2025, Some(21), 2025)), Ok(()));
+    assert_eq!(check(payload(25, 2025, None, 2025)), Ok(()));
+
+    assert_eq!(check(payload(20, 2025, Some(26), 2025)), Err(TemporalViolation::FutureSurvey));
+    assert_eq!(check(payload(20, 2025, Some(1), 2026)), Err(TemporalViolation::FutureSurvey));
+    assert_eq!(
+        check(payload(10, 2025, Some(20), 2025)),
+        Err(TemporalViolation::StaleSurvey { max_age_weeks: 4 })
+    );
+    assert_eq!(check(payload(26, 2025, None, 2025)), Err(TemporalViolation::FutureInstall));
+    assert_eq!(check(payload(24, 2025, Some(23), 2025)), Err(TemporalViolation::InstallAfterSurvey));
+
+    // 2020 has 53 ISO weeks, 2025 has 52.
+    assert_eq!(check(payload(53, 2020, Some(25), 2025)), Ok(()));
+    assert_eq!(
+        check(payload(53, 2025, Some(25), 2025)),
+        Err(TemporalViolation::InvalidWeek { field: "woi", year: 2025, week: 53 })
+    );
+    assert_eq!(
+        check(payload(20, 2025, Some(0), 2025)),
+        Err(TemporalViolation::InvalidWeek { field: "wos", year: 2025, week: 0 })
+    );
+    assert_eq!(check(payload(-1, 2025, Some(25), 2025)).unwrap_err().kind(), "invalid_week");
+
+    // Omaha measurements carry no install week.
+    let without_install = |p: MyPayload| MyPayload { woi: None, yoi: None, ..p };
+    assert_eq!(check(without_install(payload(0, 0, Some(25), 2025))), Ok(()));
+    assert_eq!(
+        check(without_install(payload(0, 0, Some(20), 2025))),
+        Err(TemporalViolation::StaleSurvey { max_age_weeks: 4 })
+    );
+    assert_eq!(
+        check(MyPayload { woi: None, ..payload(20, 2025, Some(25), 2025) }),
+        Err(TemporalViolation::IncompleteInstall)
+    );
+}
+
+#[test]
+fn future_tolerance_covers_clients_ahead_of_the_server() {
+    // Sunday of ISO week 25; clients east of UTC may already be in week 26.
+    let sunday = NaiveDate::from_ymd_opt(2025, 6, 22).unwrap();
+    let survey = payload(20, 2025, Some(26), 2025);
+    let strict = TemporalPolicy {
+        future_tolerance_days: 0,
+        max_survey_age_weeks: 12,
+    };
+    assert_eq!(strict.check(&survey, sunday), Err(TemporalViolation::FutureSurvey));
+    assert_eq!(TemporalPolicy::default().check(&survey, sunday), Ok(()));
+}
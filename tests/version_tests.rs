@@ -0,0 +1,114 @@
+// tests/version_tests.rs
+
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use serde_json::Value;
+use std::sync::Arc;
+
+use telemetry_events::auth::{AuthMiddleware, SERVICE_KEY_HEADER};
+use telemetry_events::models::DBPool;
+use telemetry_events::payload::MyPayload;
+use telemetry_events::query::get_events;
+use telemetry_events::telemetry_event::insert_events_unnest;
+use telemetry_events::version::Version;
+
+use common::{connect_test_db, test_db_configured, test_payload};
+
+const TEST_SERVICE_KEY: &str = "test-service-key";
+
+fn version(major: i32, minor: Option<i32>, build: Option<i32>, patch: Option<i32>) -> Option<Version> {
+    Some(Version { major, minor, build, patch })
+}
+
+#[actix_web::test]
+async fn versions_parse_leniently() {
+    assert_eq!(Version::parse("1.80.113"), version(1, Some(80), Some(113), None));
+    assert_eq!(Version::parse("137.1.80.113"), version(137, Some(1), Some(80), Some(113)));
+    assert_eq!(Version::parse(" v1.80.113-beta.2"), version(1, Some(80), Some(113), None));
+    assert_eq!(Version::parse("1.80.113 Beta"), version(1, Some(80), Some(113), None));
+    assert_eq!(Version::parse("1.80x.2"), version(1, Some(80), None, None));
+    assert_eq!(Version::parse("1..3"), version(1, None, None, None));
+    assert_eq!(Version::parse("1.2.3.4.5"), version(1, Some(2), Some(3), Some(4)));
+    assert_eq!(Version::parse("99999999999.1"), None);
+    assert_eq!(Version::parse("1234567890.1"), None);
+    assert_eq!(Version::parse("1.1234567890"), version(1, None, None, None));
+    assert_eq!(Version::parse("1.123456789"), version(1, Some(123456789), None, None));
+    assert_eq!(Version::parse("nightly"), None);
+    assert_eq!(Version::parse(""), None);
+
+    assert!(Version::parse("1.70").unwrap().padded() < Version::parse("1.70.1").unwrap().padded());
+    assert_eq!(Version::parse("1.70").unwrap().padded(), Version::parse("1.70.0").unwrap().padded());
+    assert_eq!(Version::parse("v1.80.113-beta").unwrap().to_string(), "1.80.113");
+}
+
+#[actix_web::test]
+async fn events_filter_by_version_range_and_latest_lines() {
+    if !test_db_configured() {
+        return;
+    }
+    let pg_pool = connect_test_db(2).await;
+    let pool = Arc::new(DBPool::from(pg_pool.clone()));
+    let metric_name = format!("Test.Version.{:08x}", rand::random::<u32>());
+    let versions = ["1.69.1", "1.70", "1.70.5", "1.71.2 Beta", "1.72.0", "unknown"];
+    let event = |version: &str, channel: &str| MyPayload {
+        channel: channel.to_string(),
+        metric_name: metric_name.clone(),
+        version: version.to_string(),
+        ..test_payload()
+    };
+    let mut events: Vec<MyPayload> = versions.iter().map(|version| event(version, "release")).collect();
+    // Newer lines elsewhere don't count towards the release channel's latest.
+    events.push(event("1.74.3", "nightly"));
+    events.push(event("999.0", "nightly"));
+    insert_events_unnest(pool.clone(), events).await.unwrap();
+
+    let components: (Option<i32>, Option<i32>, Option<i32>, Option<i32>) =
+        sqlx::query_as("SELECT major, minor, build, patch FROM telemetry_dim_version WHERE value = '1.71.2 Beta'")
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap();
+    assert_eq!(components, (Some(1), Some(71), Some(2), None));
+
+    let app = test::init_service(
+        App::new().app_data(web::Data::from(pool)).service(
+            web::resource("/api/v1/events")
+                .wrap(AuthMiddleware::with_key(TEST_SERVICE_KEY))
+                .route(web::get().to(get_events)),
+        ),
+    )
+    .await;
+    let get = |filters: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/events?metric_name={}&{}", metric_name, filters))
+            .insert_header((SERVICE_KEY_HEADER, TEST_SERVICE_KEY))
+            .to_request()
+    };
+    macro_rules! versions {
+        ($filters:expr) => {{
+            let rows: Value = test::call_and_read_body_json(&app, get($filters)).await;
+            let mut found: Vec<String> =
+                rows.as_array().unwrap().iter().map(|row| row["version"].as_str().unwrap().to_string()).collect();
+            found.sort();
+            found
+        }};
+    }
+
+    assert_eq!(versions!("channel=release&version_min=1.70"), vec!["1.70", "1.70.5", "1.71.2 Beta", "1.72.0"]);
+    assert_eq!(versions!("channel=release&version_min=1.70.1&version_max=1.71.9"), vec!["1.70.5", "1.71.2 Beta"]);
+    assert_eq!(versions!("channel=release&latest_lines=2"), vec!["1.71.2 Beta", "1.72.0"]);
+    assert_eq!(versions!("channel=release&latest_lines=2&version_max=1.71"), vec!["1.69.1", "1.70", "1.70.5"]);
+    assert_eq!(versions!("latest_lines=1"), vec!["999.0"]);
+    assert_eq!(versions!("channel=release&limit=10").len(), versions.len());
+
+    let resp = test::call_service(&app, get("version_min=latest")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, get("latest_lines=0")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    sqlx::query("DELETE FROM telemetry_events WHERE metric_name = $1")
+        .bind(&metric_name)
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+}